@@ -23,6 +23,19 @@ pub struct Args {
     )]
     pub example_config: bool,
 
+    #[arg(
+        short = 'n',
+        long = "dry-run",
+        help = "Resolve every patch and show the changes without writing output"
+    )]
+    pub dry_run: bool,
+
+    #[arg(
+        long,
+        help = "Show the instructions at each patch site before and after patching"
+    )]
+    pub disasm: bool,
+
     #[cfg(windows)]
     #[arg(short = 'k', long, help = "No Pause")]
     pub no_pause: bool,
@@ -38,4 +51,155 @@ pub enum Commands {
         #[arg(short = 'i', long = "int", help = "Patch config index")]
         index: Option<usize>,
     },
+    /// Restore a patched file to its original bytes
+    Revert {
+        #[arg(help = "Patched binary path")]
+        input: PathBuf,
+
+        #[arg(
+            short = 'o',
+            long,
+            help = "Write the restored file here instead of in place"
+        )]
+        output: Option<PathBuf>,
+
+        #[arg(
+            short = 'j',
+            long,
+            help = "Undo journal path (defaults to <input>.hexsaly-undo.json)",
+            conflicts_with = "index"
+        )]
+        journal: Option<PathBuf>,
+
+        #[arg(
+            short = 'i',
+            long = "int",
+            help = "Revert using the expect bytes of this patch config index instead of a journal"
+        )]
+        index: Option<usize>,
+    },
+    /// List or restore backups made before patching in place
+    Backups {
+        #[arg(help = "Patched binary path")]
+        input: PathBuf,
+
+        #[arg(
+            short = 'r',
+            long,
+            help = "Restore the backup with this number from the list"
+        )]
+        restore: Option<usize>,
+    },
+    /// Save the changes a config entry makes as an IPS, UPS or BPS patch file
+    Export {
+        #[arg(
+            help = "Patch file to write; the format comes from its .ips, .ups or .bps extension"
+        )]
+        patch: PathBuf,
+
+        #[arg(short = 'i', long = "int", help = "Patch config index")]
+        index: Option<usize>,
+    },
+    /// Apply an IPS, UPS or BPS patch file
+    ApplyPatchfile {
+        #[arg(help = "IPS, UPS or BPS patch file")]
+        patch: PathBuf,
+
+        #[arg(help = "Binary to patch")]
+        input: PathBuf,
+
+        #[arg(
+            short = 'o',
+            long,
+            help = "Write the patched file here instead of in place"
+        )]
+        output: Option<PathBuf>,
+    },
+    /// Write a config that turns one file into another
+    Diff {
+        #[arg(help = "Original binary path")]
+        original: PathBuf,
+
+        #[arg(help = "Patched binary path")]
+        patched: PathBuf,
+
+        #[arg(
+            short = 'o',
+            long,
+            help = "Config file to write",
+            default_value = "diff_config.json"
+        )]
+        output: PathBuf,
+
+        #[arg(
+            short = 'w',
+            long,
+            help = "Locate patches by unique wildcard signatures instead of offsets"
+        )]
+        wildcards: bool,
+    },
+    /// Print a signature that matches only one offset or method
+    Sig {
+        #[arg(help = "Binary path")]
+        input: PathBuf,
+
+        #[arg(
+            help = "File offset to sign, e.g. 0x1234",
+            required_unless_present = "method",
+            conflicts_with = "method"
+        )]
+        offset: Option<String>,
+
+        #[arg(short = 'm', long, help = "Method name to sign, looked up in --dump")]
+        method: Option<String>,
+
+        #[arg(
+            short = 'd',
+            long = "dump",
+            help = "dump.cs, script.json or stringliteral.json to resolve --method with"
+        )]
+        dump: Vec<String>,
+
+        #[arg(
+            short = 'a',
+            long,
+            help = "Universal binary slice, or the instruction set of a raw file (arm64, x86_64)"
+        )]
+        arch: Option<String>,
+
+        #[arg(
+            short = 'l',
+            long = "len",
+            help = "Bytes from the site the signature must cover, such as the patch length",
+            default_value_t = 8
+        )]
+        min_len: usize,
+    },
+    /// Move the patches of a config entry from one build of a binary to another
+    Port {
+        #[arg(help = "Binary the config was written for")]
+        old: PathBuf,
+
+        #[arg(help = "New build of the binary")]
+        new: PathBuf,
+
+        #[arg(short = 'i', long = "int", help = "Patch config index")]
+        index: Option<usize>,
+
+        #[arg(
+            short = 'o',
+            long,
+            help = "Ported config file to write",
+            default_value = "ported_config.json"
+        )]
+        output: PathBuf,
+
+        #[arg(
+            short = 'r',
+            long,
+            help = "Report of what was ported and what needs checking",
+            default_value = "port_report.txt"
+        )]
+        report: PathBuf,
+    },
 }
@@ -0,0 +1,229 @@
+use crate::cli::patch::read_input;
+use crate::func::atomic::write_atomic;
+use crate::func::binary::{Binary, Isa};
+use crate::func::config::FileFormat;
+use crate::func::hex::format_bytes;
+use crate::func::logger::*;
+use crate::func::signature::{unique_signature, MAX_SIGNATURE_LEN};
+use serde::Serialize;
+use std::io;
+use std::path::Path;
+
+/// Changed runs closer than this are written as one patch, unchanged bytes included.
+const MERGE_GAP: usize = 8;
+
+/// The config written by `diff`, in the same shape `read_config` accepts.
+#[derive(Serialize)]
+struct GeneratedConfig {
+    #[serde(rename = "Hexsaly")]
+    hexsaly: GeneratedHexsaly,
+}
+
+#[derive(Serialize)]
+struct GeneratedHexsaly {
+    style: bool,
+    menu: bool,
+    files: Vec<GeneratedFile>,
+}
+
+#[derive(Serialize)]
+struct GeneratedFile {
+    title: String,
+    input: String,
+    output: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'static str>,
+    patches: Vec<GeneratedPatch>,
+}
+
+#[derive(Serialize)]
+struct GeneratedPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wildcard: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    position: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expect: Option<String>,
+    hex_replace: String,
+}
+
+/// Ranges where `patched` differs from `original`, merging runs less than `MERGE_GAP` apart.
+fn changed_groups(original: &[u8], patched: &[u8]) -> Vec<(usize, usize)> {
+    let mut groups: Vec<(usize, usize)> = Vec::new();
+    let mut i = 0;
+    while i < original.len() {
+        if original[i] == patched[i] {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < original.len() && original[i] != patched[i] {
+            i += 1;
+        }
+        match groups.last_mut() {
+            Some(last) if start - last.1 < MERGE_GAP => last.1 = i,
+            _ => groups.push((start, i)),
+        }
+    }
+    groups
+}
+
+/// One `hex_replace` patch per changed group, applying each to `data` as it goes. With
+/// `wildcards`, a patch is located by a signature unique in `data` at that point, or by its
+/// offset when there is none.
+fn generate_patches(
+    data: &mut [u8],
+    target: &[u8],
+    isa: Isa,
+    wildcards: bool,
+    log_style: bool,
+) -> Vec<GeneratedPatch> {
+    let mut patches = Vec::new();
+    for (start, end) in changed_groups(data, target) {
+        let new_bytes = &target[start..end];
+        let signature = if wildcards {
+            // Earlier patches are already applied when this one is located
+            match unique_signature(data, start, end - start, MAX_SIGNATURE_LEN, isa) {
+                Ok(signature) => Some(signature),
+                Err(e) => {
+                    log_signature_fallback(start, &e, log_style);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        patches.push(match signature {
+            Some(signature) => GeneratedPatch {
+                offset: None,
+                wildcard: Some(signature.pattern),
+                position: Some(signature.position).filter(|&position| position != 0),
+                expect: None,
+                hex_replace: format_bytes(new_bytes),
+            },
+            None => GeneratedPatch {
+                offset: Some(format!("0x{:X}", start)),
+                wildcard: None,
+                position: None,
+                expect: Some(format_bytes(&data[start..end])),
+                hex_replace: format_bytes(new_bytes),
+            },
+        });
+        data[start..end].copy_from_slice(new_bytes);
+    }
+    patches
+}
+
+/// Compares `original` with `patched` and writes a config to `config` that turns one into the
+/// other with `hex_replace` patches. With `wildcards`, each patch is located by a signature that
+/// is unique in the file at the point it is applied, falling back to its offset when none is.
+pub fn diff_code(
+    original: &str,
+    patched: &str,
+    config: &Path,
+    wildcards: bool,
+    log_style: bool,
+) -> Result<(), io::Error> {
+    let mut data = read_input(original)?;
+    let target = read_input(patched)?;
+    if data.len() != target.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "'{}' is {} bytes but '{}' is {}; only same-size files can be diffed into hex_replace patches",
+                original,
+                data.len(),
+                patched,
+                target.len()
+            ),
+        ));
+    }
+
+    // Changes the write guard would refuse, such as in ELF section headers, need `raw`
+    let binary = Binary::load(&data, FileFormat::Auto).unwrap_or(Binary::Raw);
+    let outside_image = changed_groups(&data, &target)
+        .iter()
+        .any(|&(start, end)| binary.check_write(start, end - start).is_err());
+    let patches = generate_patches(&mut data, &target, binary.isa(), wildcards, log_style);
+
+    let title = Path::new(patched).file_name().map_or_else(
+        || patched.to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    let generated = GeneratedConfig {
+        hexsaly: GeneratedHexsaly {
+            style: true,
+            menu: false,
+            files: vec![GeneratedFile {
+                title,
+                input: original.to_string(),
+                output: patched.to_string(),
+                format: outside_image.then_some("raw"),
+                patches,
+            }],
+        },
+    };
+    let count = generated.hexsaly.files[0].patches.len();
+    let json = serde_json::to_string_pretty(&generated)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_atomic(config, json.as_bytes(), None, false)?;
+    log_diff_done(&config.display().to_string(), count, log_style);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func::fixtures::distinct;
+
+    #[test]
+    fn nearby_changes_are_merged() {
+        let original = vec![0; 64];
+        let mut patched = original.clone();
+        patched[10] = 1;
+        patched[14] = 1;
+        patched[40] = 1;
+        assert_eq!(changed_groups(&original, &patched), [(10, 15), (40, 41)]);
+    }
+
+    #[test]
+    fn offset_patches_expect_the_original() {
+        let mut data = distinct(512);
+        let mut target = data.clone();
+        target[0x100..0x104].fill(0x90);
+        let patches = generate_patches(&mut data, &target, Isa::Unknown, false, false);
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].offset.as_deref(), Some("0x100"));
+        assert_eq!(patches[0].expect.as_deref(), Some("00 80 00 81"));
+        assert_eq!(patches[0].hex_replace, "90 90 90 90");
+        assert_eq!(data, target);
+    }
+
+    #[test]
+    fn wildcard_patches_use_signatures() {
+        let mut data = distinct(512);
+        let mut target = data.clone();
+        target[0x100..0x104].fill(0x90);
+        let patches = generate_patches(&mut data, &target, Isa::Unknown, true, false);
+        assert_eq!(
+            patches[0].wildcard.as_deref(),
+            Some("00 80 00 81 00 82 00 83")
+        );
+        assert_eq!(patches[0].offset, None);
+    }
+
+    #[test]
+    fn run_longer_than_a_signature_falls_back_to_offset() {
+        let mut data = distinct(2048);
+        let mut target = data.clone();
+        target[0x100..0x100 + MAX_SIGNATURE_LEN + 172].fill(0x90);
+        let patches = generate_patches(&mut data, &target, Isa::Unknown, true, false);
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].wildcard, None);
+        assert_eq!(patches[0].offset.as_deref(), Some("0x100"));
+        assert_eq!(data, target);
+    }
+}
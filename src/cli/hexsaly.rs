@@ -1,9 +1,18 @@
 use crate::cli::args::{Args, Commands};
+use crate::cli::diff::diff_code;
 use crate::cli::patch::*;
+use crate::cli::patchfile::{apply_patchfile_code, export_code};
+use crate::cli::port::port_code;
+use crate::cli::revert::{journal_path, revert_code, revert_with_patches};
+use crate::cli::sig::{sig_code, SigTarget};
+use crate::func::backup::{list_backups, restore_backup};
+use crate::func::config::{BackupPolicy, FileEntry, MismatchPolicy};
 use crate::func::header::*;
+use crate::func::logger::*;
 use clap::Parser;
 use colored::*;
 use std::fs;
+use std::path::{Path, PathBuf};
 
 #[cfg(windows)]
 pub fn pause() {
@@ -34,6 +43,139 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
         return write_example_config();
     }
 
+    if let Some(Commands::Revert {
+        ref input,
+        ref output,
+        ref journal,
+        index: None,
+    }) = args.command
+    {
+        let target = input.to_str().ok_or("Invalid input path")?;
+        let output = match output {
+            Some(output) => output.to_str().ok_or("Invalid output path")?,
+            None => target,
+        };
+        let journal = match journal {
+            Some(journal) => Some(journal.to_str().ok_or("Invalid journal path")?),
+            None => None,
+        };
+        revert_code(target, output, journal, true)?;
+        pause();
+        return Ok(());
+    }
+
+    if let Some(Commands::Backups { ref input, restore }) = args.command {
+        // Backup directories come from the config when there is one
+        let config_path = args.config.as_ref().expect("Config path is not set");
+        let (dirs, log_style) = if config_path.exists() {
+            let config = read_config(config_path)?;
+            let dirs = std::iter::once(&config.hexsaly.backup)
+                .chain(
+                    config
+                        .hexsaly
+                        .files
+                        .iter()
+                        .filter_map(|f| f.backup.as_ref()),
+                )
+                .filter_map(|policy| match policy {
+                    BackupPolicy::Directory { path, .. } => Some(PathBuf::from(path)),
+                    _ => None,
+                })
+                .collect();
+            (dirs, config.hexsaly.style)
+        } else {
+            (Vec::new(), true)
+        };
+
+        let backups = list_backups(input, &dirs)?;
+        match restore {
+            Some(number) => {
+                let backup = number
+                    .checked_sub(1)
+                    .and_then(|i| backups.get(i))
+                    .ok_or("Invalid backup number")?;
+                restore_backup(backup, input)?;
+                // An undo journal no longer describes the restored file
+                let journal = journal_path(&input.to_string_lossy());
+                if Path::new(&journal).exists() {
+                    fs::remove_file(journal)?;
+                }
+                log_backup_restored(
+                    &backup.display().to_string(),
+                    &input.display().to_string(),
+                    log_style,
+                );
+            }
+            None if backups.is_empty() => {
+                log_patch_skip(&input.display().to_string(), "No backups found", log_style);
+            }
+            None => {
+                for (i, backup) in backups.iter().enumerate() {
+                    log_backup_entry(i + 1, &backup.display().to_string(), log_style);
+                }
+            }
+        }
+        pause();
+        return Ok(());
+    }
+
+    if let Some(Commands::Diff {
+        ref original,
+        ref patched,
+        ref output,
+        wildcards,
+    }) = args.command
+    {
+        let original = original.to_str().ok_or("Invalid original path")?;
+        let patched = patched.to_str().ok_or("Invalid patched path")?;
+        diff_code(original, patched, output, wildcards, true)?;
+        pause();
+        return Ok(());
+    }
+
+    if let Some(Commands::Sig {
+        ref input,
+        ref offset,
+        ref method,
+        ref dump,
+        ref arch,
+        min_len,
+    }) = args.command
+    {
+        let input = input.to_str().ok_or("Invalid input path")?;
+        let target = match (offset, method) {
+            (Some(offset), _) => SigTarget::Offset(offset.clone()),
+            (None, Some(method)) => SigTarget::MethodName(method.clone()),
+            (None, None) => return Err("Give an offset or a method name".into()),
+        };
+        sig_code(input, &target, dump, arch.as_deref(), min_len, true)?;
+        pause();
+        return Ok(());
+    }
+
+    if let Some(Commands::ApplyPatchfile {
+        ref patch,
+        ref input,
+        ref output,
+    }) = args.command
+    {
+        let config_path = args.config.as_ref().expect("Config path is not set");
+        let (backup, log_style) = if config_path.exists() {
+            let config = read_config(config_path)?;
+            (config.hexsaly.backup, config.hexsaly.style)
+        } else {
+            (BackupPolicy::default(), true)
+        };
+        let target = input.to_str().ok_or("Invalid input path")?;
+        let output = match output {
+            Some(output) => output.to_str().ok_or("Invalid output path")?,
+            None => target,
+        };
+        apply_patchfile_code(patch, target, output, &backup, log_style)?;
+        pause();
+        return Ok(());
+    }
+
     if !args
         .config
         .as_ref()
@@ -48,23 +190,132 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     let config_path = fs::canonicalize(args.config.as_ref().expect("Config path is not set"))?;
-    let (files, log_style, use_menu) = read_config(&config_path)?;
+    let config = read_config(&config_path)?;
+    let files = config.hexsaly.files;
+    let log_style = config.hexsaly.style;
+    let use_menu = config.hexsaly.menu;
+    let default_on_mismatch = config.hexsaly.on_mismatch;
+    let journal = config.hexsaly.journal;
+    let default_backup = config.hexsaly.backup;
+    let default_preserve_times = config.hexsaly.preserve_times;
+    let symbol_cache = config.hexsaly.symbol_cache;
+    let disasm = config.hexsaly.disasm || args.disasm;
+
+    let options_for = |file_config: &FileEntry| PatchOptions {
+        on_mismatch: file_config.on_mismatch.unwrap_or(default_on_mismatch),
+        journal,
+        backup: file_config
+            .backup
+            .clone()
+            .unwrap_or_else(|| default_backup.clone()),
+        preserve_times: file_config.preserve_times.unwrap_or(default_preserve_times),
+        format: file_config.format,
+        update_checksum: file_config.update_checksum,
+        arch: file_config.arch.clone(),
+        symbol_cache,
+        method_address: file_config.method_address,
+        disasm,
+        dry_run: args.dry_run,
+    };
+
+    if let Some(Commands::Export { ref patch, index }) = args.command {
+        let selected_index = match index {
+            Some(idx) => {
+                files.get(idx).ok_or("Invalid index")?;
+                idx
+            }
+            None if files.len() == 1 => 0,
+            None => display_menu(&files, None)?,
+        };
+        let file_config = &files[selected_index];
+        export_code(
+            &file_config.input,
+            &file_config.patches,
+            &file_config.symbol_sources(),
+            &options_for(file_config),
+            patch,
+            log_style,
+        )?;
+        pause();
+        return Ok(());
+    }
+
+    if let Some(Commands::Port {
+        ref old,
+        ref new,
+        index,
+        ref output,
+        ref report,
+    }) = args.command
+    {
+        let old = old.to_str().ok_or("Invalid old binary path")?;
+        let new = new.to_str().ok_or("Invalid new binary path")?;
+        let selected_index = match index {
+            Some(idx) => {
+                files.get(idx).ok_or("Invalid index")?;
+                idx
+            }
+            None if files.len() == 1 => 0,
+            None => display_menu(&files, None)?,
+        };
+        port_code(
+            &config_path,
+            selected_index,
+            old,
+            new,
+            output,
+            report,
+            log_style,
+        )?;
+        pause();
+        return Ok(());
+    }
+
+    if let Some(Commands::Revert {
+        ref input,
+        ref output,
+        index: Some(idx),
+        ..
+    }) = args.command
+    {
+        let file = files.get(idx).ok_or("Invalid index")?;
+        let target = input.to_str().ok_or("Invalid input path")?;
+        let output = match output {
+            Some(output) => output.to_str().ok_or("Invalid output path")?,
+            None => target,
+        };
+        revert_with_patches(
+            target,
+            output,
+            &file.patches,
+            &file.symbol_sources(),
+            &PatchOptions {
+                format: file.format,
+                arch: file.arch.clone(),
+                symbol_cache,
+                method_address: file.method_address,
+                ..PatchOptions::default()
+            },
+            log_style,
+        )?;
+        pause();
+        return Ok(());
+    }
 
     let file_configs = if let Some(Commands::Open { ref input, index }) = args.command {
         let input_str = input.to_str().ok_or("Invalid input path")?;
 
-        if let Some(idx) = index {
-            let mut config = files.get(idx).ok_or("Invalid index")?.clone();
-            config["input"] = input_str.into();
-            config["output"] = input_str.into();
-            vec![config]
-        } else {
-            let selected_index = display_menu(&files, None)?;
-            let mut config = files[selected_index].clone();
-            config["input"] = input_str.into();
-            config["output"] = input_str.into();
-            vec![config]
-        }
+        let selected_index = match index {
+            Some(idx) => {
+                files.get(idx).ok_or("Invalid index")?;
+                idx
+            }
+            None => display_menu(&files, None)?,
+        };
+        let mut config = files[selected_index].clone();
+        config.input = input_str.to_string();
+        config.output = Some(input_str.to_string());
+        vec![config]
     } else if use_menu {
         let selected_index = display_menu(&files, None)?;
         vec![files[selected_index].clone()]
@@ -73,26 +324,27 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     for file_config in file_configs {
-        let input = file_config["input"]
-            .as_str()
-            .or_else(|| file_config["open"].as_str())
-            .ok_or("Missing input or open in config")?;
+        let input = file_config.input.as_str();
+        let output = file_config.output();
+        let symbols = file_config.symbol_sources();
+        let on_mismatch = file_config.on_mismatch.unwrap_or(default_on_mismatch);
+        let patches = &file_config.patches;
+        let options = options_for(&file_config);
 
-        let output = if let Some(Commands::Open { input, .. }) = &args.command {
-            input.to_str().ok_or("Invalid input path")?
+        let result = if args.dry_run {
+            dry_run_code(input, patches, &symbols, &options, log_style).map(|_| ())
         } else {
-            file_config["output"].as_str().unwrap_or(input)
+            patch_code(input, output, patches, &symbols, &options, log_style)
         };
 
-        let patch_list = &file_config["patches"];
-        let dump_cs = file_config["dump_cs"].as_str();
-        let require = file_config["require"].as_bool().unwrap_or(false);
-
-        if let Err(e) = patch_code(input, output, patch_list, dump_cs, log_style) {
-            eprintln!("{}", format!("Error: {}", e).red());
-            if require {
+        if let Err(e) = result {
+            let mismatch = e
+                .get_ref()
+                .is_some_and(|inner| inner.is::<ExpectMismatch>());
+            if file_config.require || (mismatch && on_mismatch == MismatchPolicy::FailRun) {
                 return Err(Box::new(e));
             }
+            eprintln!("{}", format!("Error: {}", e).red());
         }
     }
     pause();
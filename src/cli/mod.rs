@@ -1,3 +1,8 @@
 pub mod args;
+pub mod diff;
 pub mod hexsaly;
 pub mod patch;
+pub mod patchfile;
+pub mod port;
+pub mod revert;
+pub mod sig;
@@ -1,22 +1,76 @@
-use crate::func::header::validate_patch_structure;
+use crate::cli::revert::write_journal;
+use crate::func::archive::{disk_path, member_path, read_member, replace_member};
+use crate::func::atomic::write_atomic;
+use crate::func::backup::create_backup;
+use crate::func::binary::{select_slice, Binary, Isa};
+use crate::func::config::{
+    Action, AddressKind, BackupPolicy, FileFormat, Locator, MismatchPolicy, Occurrence, Patch,
+};
+use crate::func::disasm::{disassemble, MAX_INSTRUCTION_LEN};
+use crate::func::dump::{CacheMode, Dump};
+use crate::func::hex::format_bytes;
 use crate::func::logger::*;
+use crate::func::pe::Pe;
+use crate::func::scan::Scanner;
 use colored::*;
-use regex::Regex;
-use serde_json::{self, Value};
-use std::fs::{self, File, OpenOptions};
-use std::io::{self, BufRead, BufReader, Read, Write};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read};
+use std::path::Path;
+
+/// A single change made to the data, recorded as the bytes before and after it.
+#[derive(Debug, Clone)]
+pub struct PatchRecord {
+    pub offset: usize,
+    pub old_bytes: Vec<u8>,
+    pub new_bytes: Vec<u8>,
+    pub inserted: bool,
+}
+
+/// Raised when the bytes at a patch site do not match the patch's `expect` pattern.
+#[derive(Debug)]
+pub struct ExpectMismatch {
+    pub offset: usize,
+    pub expected: String,
+    pub found: String,
+}
+
+impl fmt::Display for ExpectMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Expected bytes {} at offset 0x{:X}, found {}",
+            self.expected, self.offset, self.found
+        )
+    }
+}
+
+impl std::error::Error for ExpectMismatch {}
+
+/// Per-file settings that control how patches are verified and the output is written.
+#[derive(Debug, Clone, Default)]
+pub struct PatchOptions {
+    pub on_mismatch: MismatchPolicy,
+    pub journal: bool,
+    pub backup: BackupPolicy,
+    pub preserve_times: bool,
+    pub format: FileFormat,
+    pub update_checksum: bool,
+    pub arch: Option<String>,
+    pub symbol_cache: bool,
+    pub method_address: AddressKind,
+    pub disasm: bool,
+    /// Set for runs that must not write anything, including the symbol cache.
+    pub dry_run: bool,
+}
 
 pub fn replace_hex_at_offset(
     data: &mut [u8],
     offset: usize,
-    repl: &str,
+    bytes: &[u8],
     log_style: bool,
 ) -> Result<(), String> {
-    let bytes: Vec<u8> = repl
-        .split_whitespace()
-        .map(|s| u8::from_str_radix(s, 16).map_err(|e| e.to_string()))
-        .collect::<Result<_, _>>()?;
-
     if offset + bytes.len() > data.len() {
         return Err(format!(
             "Replacement exceeds data size at offset 0x{:X}",
@@ -24,7 +78,7 @@ pub fn replace_hex_at_offset(
         ));
     }
 
-    data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    data[offset..offset + bytes.len()].copy_from_slice(bytes);
     log_offset(offset, log_style, "Patching");
     Ok(())
 }
@@ -32,14 +86,9 @@ pub fn replace_hex_at_offset(
 pub fn insert_hex_at_offset(
     data: &mut Vec<u8>,
     offset: usize,
-    repl: &str,
+    bytes: &[u8],
     log_style: bool,
 ) -> Result<(), String> {
-    let bytes: Vec<u8> = repl
-        .split_whitespace()
-        .map(|s| u8::from_str_radix(s, 16).map_err(|e| e.to_string()))
-        .collect::<Result<_, _>>()?;
-
     if offset > data.len() {
         return Err(format!(
             "Insertion exceeds data size at offset 0x{:X}",
@@ -52,72 +101,109 @@ pub fn insert_hex_at_offset(
     Ok(())
 }
 
-pub fn wildcard_pattern_scan(
-    data: &[u8],
-    pattern: &str,
-    log_style: bool,
-) -> Option<(usize, Vec<u8>)> {
-    let pattern_bytes: Vec<Option<u8>> = pattern
-        .split_whitespace()
-        .map(|s| {
-            if s == "??" {
-                None
-            } else {
-                Some(u8::from_str_radix(s, 16).ok()?)
+/// Picks the matches a patch applies to according to its `occurrence`.
+fn select_occurrences<T>(mut hits: Vec<T>, occurrence: Occurrence) -> Result<Vec<T>, String> {
+    let count = hits.len();
+    match occurrence {
+        Occurrence::First => {
+            hits.truncate(1);
+            Ok(hits)
+        }
+        Occurrence::All => Ok(hits),
+        Occurrence::Last => Ok(hits.pop().into_iter().collect()),
+        Occurrence::Nth(n) => {
+            if n > count {
+                return Err(format!(
+                    "Match {} requested but only {} found for wildcard.",
+                    n, count
+                ));
             }
-        })
-        .collect();
-
-    'outer: for i in 0..=data.len() - pattern_bytes.len() {
-        for (j, &pat_byte) in pattern_bytes.iter().enumerate() {
-            if let Some(pat_byte) = pat_byte {
-                if data[i + j] != pat_byte {
-                    continue 'outer;
-                }
+            Ok(vec![hits.swap_remove(n - 1)])
+        }
+        Occurrence::Exactly(n) => {
+            if n != count {
+                return Err(format!(
+                    "Expected exactly {} matches for wildcard, found {}.",
+                    n, count
+                ));
             }
+            Ok(hits)
         }
-        log_pattern_found(pattern, log_style);
-        // Return both offset and matched bytes
-        return Some((i, data[i..i + pattern_bytes.len()].to_vec()));
     }
-    None
 }
 
+/// Looks up a method in the symbol sources by name, `Class::Method` or a fully qualified
+/// signature such as `Namespace.Class::Method(int, string)`, returning the file offset of the
+/// address column chosen by `kind`.
 pub fn find_offset_by_method_name(
     method_name: &str,
-    dump_path: &str,
+    dump: &Dump,
+    binary: &Binary,
+    kind: AddressKind,
     log_style: bool,
-) -> Result<Option<usize>, io::Error> {
-    let file = File::open(dump_path)?;
-    let reader = BufReader::new(file);
-    let offset_regex = Regex::new(r"Offset:\s*0x([0-9A-Fa-f]+)").unwrap();
-
-    let mut previous_line = String::new();
-
-    for line in reader.lines() {
-        let line = line?;
-        if line.contains(method_name) {
-            if let Some(caps) = offset_regex.captures(&previous_line) {
-                let offset = usize::from_str_radix(&caps[1], 16).unwrap();
-                log_method_found(method_name, offset, log_style);
-                return Ok(Some(offset));
-            } else {
-                log_no_offset_found(method_name, log_style);
-                return Ok(None);
-            }
+) -> Option<usize> {
+    let method = match dump.find(method_name) {
+        Ok(method) => method,
+        Err(e) => {
+            log_patch_skip(method_name, &e, log_style);
+            return None;
+        }
+    };
+    let resolved = match (kind, method.offset, method.rva, method.va) {
+        (AddressKind::Offset, Some(offset), _, _) => Ok(offset as usize),
+        // `script.json` only knows the RVA, which the binary's layout turns into an offset
+        (AddressKind::Offset | AddressKind::Rva, _, Some(rva), _) => binary.rva_to_offset(rva),
+        (AddressKind::Va, _, _, Some(va)) => binary.va_to_offset(va),
+        (AddressKind::Offset, None, None, _) => {
+            log_no_offset_found(&method.qualified_name(), log_style);
+            return None;
+        }
+        (AddressKind::Rva, ..) => Err(format!("{} has no RVA", method.qualified_name())),
+        (AddressKind::Va, ..) => Err(format!("{} has no VA", method.qualified_name())),
+    };
+    match resolved {
+        Ok(offset) => {
+            log_method_found(&method.qualified_name(), offset, log_style);
+            Some(offset)
+        }
+        Err(e) => {
+            log_patch_skip(method_name, &e, log_style);
+            None
+        }
+    }
+}
+
+/// Looks up a string literal in the symbol sources, returning the file offset of its
+/// metadata reference.
+pub fn find_offset_by_string_literal(
+    value: &str,
+    dump: &Dump,
+    binary: &Binary,
+    log_style: bool,
+) -> Option<usize> {
+    let resolved = dump
+        .find_string(value)
+        .and_then(|literal| binary.rva_to_offset(literal.rva));
+    match resolved {
+        Ok(offset) => {
+            log_string_literal_found(value, offset, log_style);
+            Some(offset)
+        }
+        Err(e) => {
+            log_patch_skip(&format!("{:?}", value), &e, log_style);
+            None
         }
-        previous_line = line;
     }
-    Ok(None)
 }
 
 pub fn apply_patch(
     data: &mut Vec<u8>,
     offset: usize,
-    patch: &Value,
+    patch: &Patch,
+    payload: &[u8],
     wildcard_bytes: Option<&[u8]>,
     log_style: bool,
-) -> Result<(), String> {
+) -> Result<PatchRecord, String> {
     if offset >= data.len() {
         return Err(format!(
             "Error: Offset 0x{:X} is out of range for the input file.",
@@ -127,153 +213,481 @@ pub fn apply_patch(
         .to_string());
     }
 
-    let process_hex = |hex: &str, matched: Option<&[u8]>| -> Result<String, String> {
-        if let (Some(pos), Some(matched)) =
-            (patch.get("position").and_then(|p| p.as_u64()), matched)
-        {
-            let pos = pos as usize;
+    let bytes = match (patch.position, wildcard_bytes) {
+        (Some(pos), Some(matched)) => {
             if pos > matched.len() {
                 return Err("Position exceeds wildcard pattern length".into());
             }
 
-            // Take bytes from wildcard up to position
-            let matched_hex: String = matched[..pos]
-                .iter()
-                .map(|b| format!("{:02X}", b))
-                .collect::<Vec<_>>()
-                .join(" ");
-
-            // Combine with hex_replace/insert values
-            Ok(format!("{} {}", matched_hex, hex))
-        } else {
-            Ok(hex.to_string())
+            // Take bytes from wildcard up to position, then the patch bytes
+            let mut bytes = matched[..pos].to_vec();
+            bytes.extend_from_slice(payload);
+            bytes
         }
+        _ => payload.to_vec(),
     };
 
-    if let Some(hex_replace) = patch.get("hex_replace") {
-        let processed_hex = process_hex(hex_replace.as_str().unwrap(), wildcard_bytes)?;
-        replace_hex_at_offset(data, offset, &processed_hex, log_style)?;
-        log_patch_action("Replaced", &processed_hex, log_style);
-    } else if let Some(hex_insert) = patch.get("hex_insert") {
-        let processed_hex = process_hex(hex_insert.as_str().unwrap(), wildcard_bytes)?;
-        insert_hex_at_offset(data, offset, &processed_hex, log_style)?;
-        log_patch_action("Inserted", &processed_hex, log_style);
-    } else {
-        return Err("Patch must contain either 'hex_replace' or 'hex_insert'.".into());
-    }
+    let record = match patch.action {
+        Action::Replace(_) => {
+            let old_bytes = data
+                .get(offset..offset + bytes.len())
+                .map(|old| old.to_vec())
+                .unwrap_or_default();
+            replace_hex_at_offset(data, offset, &bytes, log_style)?;
+            log_patch_action("Replaced", &format_bytes(&bytes), log_style);
+            PatchRecord {
+                offset,
+                old_bytes,
+                new_bytes: bytes,
+                inserted: false,
+            }
+        }
+        Action::Insert(_) => {
+            insert_hex_at_offset(data, offset, &bytes, log_style)?;
+            log_patch_action("Inserted", &format_bytes(&bytes), log_style);
+            PatchRecord {
+                offset,
+                old_bytes: Vec::new(),
+                new_bytes: bytes,
+                inserted: true,
+            }
+        }
+    };
 
-    Ok(())
+    Ok(record)
 }
 
-pub fn patch_code(
-    input: &str,
-    output: &str,
-    patch_list: &Value,
-    dump_path: Option<&str>,
-    log_style: bool,
-) -> Result<(), io::Error> {
+/// Reads `input`, which may also name a member inside an archive such as
+/// `game.apk!lib/arm64-v8a/libil2cpp.so`.
+pub fn read_input(input: &str) -> Result<Vec<u8>, io::Error> {
+    let file = disk_path(input);
     // Check if input file exists and is readable
-    if !std::path::Path::new(input).exists() {
+    if !std::path::Path::new(file).exists() {
         return Err(io::Error::new(
             io::ErrorKind::NotFound,
-            format!("Input file '{}' does not exist", input),
+            format!("Input file '{}' does not exist", file),
         ));
     }
-    let input_metadata = fs::metadata(input)?;
+    let input_metadata = fs::metadata(file)?;
     if input_metadata.permissions().readonly() {
         return Err(io::Error::new(
             io::ErrorKind::PermissionDenied,
-            format!("Input file '{}' is not readable", input),
+            format!("Input file '{}' is not readable", file),
         ));
     }
 
-    // Check if output file is writable
-    if std::path::Path::new(output).exists() {
-        let output_metadata = fs::metadata(output)?;
-        if output_metadata.permissions().readonly() {
-            return Err(io::Error::new(
-                io::ErrorKind::PermissionDenied,
-                format!("Output file '{}' is not writable", output),
-            ));
-        }
-    } else {
-        // Try to create the output file to check if it's writable
-        let _ = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(output)?;
-    }
-
     // Open input file with read permissions
-    let mut input_file = OpenOptions::new().read(true).open(input)?;
+    let mut input_file = OpenOptions::new().read(true).open(file)?;
     let mut data = Vec::new();
     input_file.read_to_end(&mut data)?;
 
-    for patch in patch_list.as_array().unwrap() {
-        if !validate_patch_structure(patch, log_style) {
-            return Ok(());
+    match member_path(input) {
+        Some(member) => read_member(&data, &member.members)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", input, e))),
+        None => Ok(data),
+    }
+}
+
+/// Writes the patched `data` of `input` to `output`. When `output` names an archive member,
+/// the archive holding `input` (or else the existing `output` archive) is rewritten with that
+/// member replaced.
+pub fn write_output(
+    input: &str,
+    output: &str,
+    data: &[u8],
+    preserve_times: bool,
+) -> Result<(), io::Error> {
+    let source = disk_path(input);
+    let input_metadata = fs::metadata(source)?;
+    let Some(member) = member_path(output) else {
+        return write_atomic(
+            Path::new(output),
+            data,
+            Some(&input_metadata),
+            preserve_times,
+        );
+    };
+
+    let base = if member_path(input).is_some() {
+        source
+    } else {
+        member.archive
+    };
+    let archive = fs::read(base)?;
+    let rebuilt = replace_member(&archive, &member.members, data)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", output, e)))?;
+    write_atomic(
+        Path::new(member.archive),
+        &rebuilt,
+        Some(&input_metadata),
+        preserve_times,
+    )
+}
+
+/// Resolves and applies every patch to `data` in order, returning what was changed. In a
+/// universal binary only the slice chosen by `options.arch` is patched, and offsets in the
+/// patches are relative to that slice.
+pub fn patch_data(
+    data: &mut Vec<u8>,
+    patches: &[Patch],
+    symbols: &[String],
+    options: &PatchOptions,
+    log_style: bool,
+) -> Result<Vec<PatchRecord>, io::Error> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let (range, arch) = select_slice(data, options.arch.as_deref()).map_err(invalid)?;
+    let Some(arch) = arch else {
+        return patch_image(data, patches, symbols, options, log_style);
+    };
+
+    // Growing a slice would shift the ones after it and break the fat header
+    if patches
+        .iter()
+        .any(|patch| matches!(patch.action, Action::Insert(_)))
+    {
+        return Err(invalid(
+            "hex_insert and asm_insert are not supported inside a universal binary slice".into(),
+        ));
+    }
+    log_slice_selected(&arch, range.start, range.len(), log_style);
+    let mut slice = data[range.clone()].to_vec();
+    let mut records = patch_image(&mut slice, patches, symbols, options, log_style)?;
+    data[range.clone()].copy_from_slice(&slice);
+    for record in &mut records {
+        record.offset += range.start;
+    }
+    Ok(records)
+}
+
+fn patch_image(
+    data: &mut Vec<u8>,
+    patches: &[Patch],
+    symbols: &[String],
+    options: &PatchOptions,
+    log_style: bool,
+) -> Result<Vec<PatchRecord>, io::Error> {
+    let mut records = Vec::new();
+    let binary = Binary::load(data, options.format)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let isa = binary.code_isa(options.arch.as_deref());
+
+    // Compile every wildcard once and find all of them in a single pass
+    let mut pattern_ids: HashMap<String, usize> = HashMap::new();
+    let mut compiled = Vec::new();
+    for patch in patches {
+        if let Locator::Wildcard(wildcard) = &patch.locator {
+            pattern_ids.entry(wildcard.to_string()).or_insert_with(|| {
+                compiled.push(wildcard.clone());
+                compiled.len() - 1
+            });
         }
+    }
+    let mut scanner = Scanner::new(data, compiled);
 
-        let offset = if let Some(method_name) = patch.get("method_name") {
-            if let Some(dump_path) = dump_path {
-                if let Some(offset) =
-                    find_offset_by_method_name(method_name.as_str().unwrap(), dump_path, log_style)?
-                {
-                    offset
-                } else {
-                    log_patch_skip(method_name.as_str().unwrap(), "Method not found", log_style);
-                    continue;
-                }
-            } else {
+    // Symbol sources are parsed and indexed once for all patches of the file
+    let needs_symbols = patches.iter().any(|patch| {
+        matches!(
+            patch.locator,
+            Locator::MethodName(_) | Locator::StringLiteral(_)
+        )
+    });
+    let dump = if needs_symbols && !symbols.is_empty() {
+        let mode = match (options.symbol_cache, options.dry_run) {
+            (false, _) => CacheMode::Off,
+            (true, true) => CacheMode::ReadOnly,
+            (true, false) => CacheMode::ReadWrite,
+        };
+        Dump::load(symbols, mode)?
+    } else {
+        Dump::default()
+    };
+
+    for patch in patches {
+        let sites: Vec<(usize, Option<Vec<u8>>)> = match &patch.locator {
+            Locator::MethodName(_) | Locator::StringLiteral(_) if symbols.is_empty() => {
                 log_patch_skip(
-                    method_name.as_str().unwrap(),
-                    "dump_path is required for method_name patches",
+                    &patch.locator.to_string(),
+                    "`dump_cs` or `symbols` is required for method_name and string_literal patches",
                     log_style,
                 );
                 continue;
             }
-        } else if let Some(offset_str) = patch.get("offset").and_then(|v| v.as_str()) {
-            match usize::from_str_radix(offset_str.trim_start_matches("0x"), 16) {
-                Ok(offset) => offset,
-                Err(e) => {
-                    log_patch_error(offset_str, &e.to_string(), log_style);
+            Locator::MethodName(method_name) => {
+                let kind = patch.address.unwrap_or(options.method_address);
+                match find_offset_by_method_name(method_name, &dump, &binary, kind, log_style) {
+                    Some(offset) => vec![(offset, None)],
+                    None => continue,
+                }
+            }
+            Locator::StringLiteral(value) => {
+                match find_offset_by_string_literal(value, &dump, &binary, log_style) {
+                    Some(offset) => vec![(offset, None)],
+                    None => continue,
+                }
+            }
+            Locator::Offset(offset) => vec![(*offset, None)],
+            Locator::Va(address) | Locator::Rva(address) => {
+                let (kind, resolved) = match patch.locator {
+                    Locator::Va(_) => ("VA", binary.va_to_offset(*address)),
+                    _ => ("RVA", binary.rva_to_offset(*address)),
+                };
+                match resolved {
+                    Ok(offset) => {
+                        log_address_resolved(kind, *address, offset, log_style);
+                        vec![(offset, None)]
+                    }
+                    Err(e) => {
+                        log_patch_skip(&patch.locator.to_string(), &e, log_style);
+                        continue;
+                    }
+                }
+            }
+            Locator::Wildcard(wildcard) => {
+                let wildcard_text = wildcard.to_string();
+                let mut hits = scanner.matches(pattern_ids[&wildcard_text], data);
+                if let Some(name) = &patch.section {
+                    match binary.section_range(name) {
+                        Ok(range) => hits.retain(|&(offset, len)| {
+                            offset >= range.start && offset + len <= range.end
+                        }),
+                        Err(e) => {
+                            log_patch_skip(&wildcard_text, &e, log_style);
+                            continue;
+                        }
+                    }
+                }
+                if hits.is_empty() {
+                    log_patch_skip(&wildcard_text, "No match found for wildcard.", log_style);
                     continue;
                 }
+                let selected = match select_occurrences(hits, patch.occurrence) {
+                    Ok(selected) => selected,
+                    Err(reason) => {
+                        log_patch_skip(&wildcard_text, &reason, log_style);
+                        continue;
+                    }
+                };
+                if patch.occurrence == Occurrence::First {
+                    log_pattern_found(&wildcard_text, log_style);
+                } else {
+                    let offsets: Vec<usize> = selected.iter().map(|&(offset, _)| offset).collect();
+                    log_pattern_matches(&wildcard_text, &offsets, log_style);
+                }
+                selected
+                    .into_iter()
+                    .map(|(offset, len)| (offset, Some(data[offset..offset + len].to_vec())))
+                    .collect()
             }
-        } else if let Some(wildcard) = patch.get("wildcard") {
-            if let Some((offset, matched_bytes)) =
-                wildcard_pattern_scan(&data, wildcard.as_str().unwrap(), log_style)
+        };
+
+        // Patch the last site first so insertions do not shift the sites still pending
+        for (offset, matched_bytes) in sites.into_iter().rev() {
+            let prefix = matched_bytes
+                .as_ref()
+                .map_or(0, |_| patch.position.unwrap_or(0));
+            let original = data.get(offset + prefix..).unwrap_or_default();
+            let payload = match patch
+                .action
+                .payload()
+                .encode(original, offset + prefix, isa)
             {
-                if let Err(e) =
-                    apply_patch(&mut data, offset, patch, Some(&matched_bytes), log_style)
-                {
-                    log_patch_error("Applying patch", &e, log_style);
+                Ok(payload) => payload,
+                Err(e) => {
+                    log_patch_skip(&patch.locator.to_string(), &e, log_style);
+                    continue;
                 }
-                continue;
-            } else {
-                log_patch_skip(
-                    wildcard.as_str().unwrap(),
-                    "No match found for wildcard.",
-                    log_style,
-                );
+            };
+            if let Err(e) = binary.check_write(offset, prefix + payload.len()) {
+                log_patch_skip(&patch.locator.to_string(), &e, log_style);
                 continue;
             }
-        } else {
+
+            if let Some(expect) = &patch.expect {
+                if !expect.matches_at(data, offset) {
+                    let end = (offset + expect.max_len()).min(data.len());
+                    let mismatch = ExpectMismatch {
+                        offset,
+                        expected: expect.to_string(),
+                        found: format_bytes(data.get(offset..end).unwrap_or_default()),
+                    };
+                    if options.on_mismatch == MismatchPolicy::Skip {
+                        log_patch_skip(
+                            &patch.locator.to_string(),
+                            &mismatch.to_string(),
+                            log_style,
+                        );
+                        continue;
+                    }
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, mismatch));
+                }
+            }
+
+            let len = prefix + payload.len();
+            let before = options.disasm.then(|| {
+                let end = (offset + len + MAX_INSTRUCTION_LEN).min(data.len());
+                data.get(offset..end).unwrap_or_default().to_vec()
+            });
+            match apply_patch(
+                data,
+                offset,
+                patch,
+                &payload,
+                matched_bytes.as_deref(),
+                log_style,
+            ) {
+                Ok(record) => {
+                    if let Some(before) = before {
+                        log_patch_disasm(data, &before, &record, isa, log_style);
+                    }
+                    if record.inserted {
+                        scanner.inserted(record.offset, record.new_bytes.len());
+                    } else {
+                        scanner.replaced(record.offset, record.new_bytes.len());
+                    }
+                    records.push(record);
+                }
+                Err(e) => log_patch_error("Applying patch", &e, log_style),
+            }
+        }
+    }
+
+    if options.update_checksum {
+        records.extend(update_pe_checksum(data, log_style));
+    }
+    if binary.is_signed() && !records.is_empty() {
+        log_signature_invalidated(log_style);
+    }
+
+    Ok(records)
+}
+
+/// Recomputes the PE optional header `CheckSum` of the patched data, returning the change.
+fn update_pe_checksum(data: &mut [u8], log_style: bool) -> Option<PatchRecord> {
+    // Inserted bytes may have moved the headers, so the patched data is parsed again
+    let pe = match Pe::parse(data) {
+        Ok(pe) => pe,
+        Err(e) => {
             log_patch_skip(
-                "unknown",
-                "Patch does not contain a valid method_name, offset, or wildcard",
+                "checksum",
+                &format!("cannot update checksum: {}", e),
                 log_style,
             );
-            continue;
+            return None;
+        }
+    };
+    let at = pe.checksum_offset;
+    let old = u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+    let new = pe.checksum(data);
+    log_checksum_updated(old, new, log_style);
+    if old == new {
+        return None;
+    }
+    data[at..at + 4].copy_from_slice(&new.to_le_bytes());
+    Some(PatchRecord {
+        offset: at,
+        old_bytes: old.to_le_bytes().to_vec(),
+        new_bytes: new.to_le_bytes().to_vec(),
+        inserted: false,
+    })
+}
+
+/// Shows the instructions a patch replaced, decoded from the `before` bytes, next to the ones
+/// it wrote into `data`.
+fn log_patch_disasm(data: &[u8], before: &[u8], record: &PatchRecord, isa: Isa, log_style: bool) {
+    let len = record.new_bytes.len();
+    let end = (record.offset + len + MAX_INSTRUCTION_LEN).min(data.len());
+    let address = record.offset as u64;
+    let before = if record.inserted {
+        Ok(vec!["(insert)".to_string()])
+    } else {
+        disassemble(before, address, len, isa)
+    };
+    match (
+        before,
+        disassemble(
+            data.get(record.offset..end).unwrap_or_default(),
+            address,
+            len,
+            isa,
+        ),
+    ) {
+        (Ok(before), Ok(after)) => log_disasm(&before, &after, log_style),
+        (Err(e), _) | (_, Err(e)) => log_disasm_unavailable(&e, log_style),
+    }
+}
+
+/// Where each change of `records` ends up in the patched output. A record holds its offset at
+/// the time it was applied, which bytes inserted later at or before it shift.
+pub fn output_offsets(records: &[PatchRecord]) -> Vec<usize> {
+    records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| {
+            records[i + 1..].iter().filter(|later| later.inserted).fold(
+                record.offset,
+                |offset, later| {
+                    if later.offset <= offset {
+                        offset + later.new_bytes.len()
+                    } else {
+                        offset
+                    }
+                },
+            )
+        })
+        .collect()
+}
+
+/// Runs every patch against an in-memory copy of `input` and reports the changes
+/// without writing anything to disk.
+pub fn dry_run_code(
+    input: &str,
+    patches: &[Patch],
+    symbols: &[String],
+    options: &PatchOptions,
+    log_style: bool,
+) -> Result<Vec<PatchRecord>, io::Error> {
+    let options = PatchOptions {
+        dry_run: true,
+        ..options.clone()
+    };
+    let mut data = read_input(input)?;
+    let records = patch_data(&mut data, patches, symbols, &options, log_style)?;
+
+    for (record, offset) in records.iter().zip(output_offsets(&records)) {
+        let old = if record.inserted {
+            "(insert)".to_string()
+        } else {
+            format_bytes(&record.old_bytes)
         };
+        log_dry_run_change(offset, &old, &format_bytes(&record.new_bytes), log_style);
+    }
+    log_dry_run_done(input, records.len(), log_style);
+    Ok(records)
+}
 
-        // Apply the patch at the calculated offset (for non-wildcard cases)
-        if let Err(e) = apply_patch(&mut data, offset, patch, None, log_style) {
-            log_patch_error("Applying patch", &e, log_style);
+pub fn patch_code(
+    input: &str,
+    output: &str,
+    patches: &[Patch],
+    symbols: &[String],
+    options: &PatchOptions,
+    log_style: bool,
+) -> Result<(), io::Error> {
+    // Check if output file is writable
+    let output_file = disk_path(output);
+    if std::path::Path::new(output_file).exists() {
+        let output_metadata = fs::metadata(output_file)?;
+        if output_metadata.permissions().readonly() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Output file '{}' is not writable", output_file),
+            ));
         }
     }
 
+    let mut data = read_input(input)?;
+    let records = patch_data(&mut data, patches, symbols, options, log_style)?;
+
     if data.is_empty() {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
@@ -281,14 +695,204 @@ pub fn patch_code(
         ));
     }
 
-    // Open output file with write permissions
-    let mut output_file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(output)?;
-    output_file.write_all(&data)?;
+    // Keep a copy of the original before it is overwritten in place
+    if disk_path(input) == output_file {
+        if let Some(backup) = create_backup(Path::new(output_file), &options.backup)? {
+            log_backup_created(&backup.display().to_string(), log_style);
+        }
+    }
+
+    write_output(input, output, &data, options.preserve_times)?;
+    if let Some(member) = member_path(output) {
+        log_archive_unsigned(member.archive, log_style);
+    }
+
+    if options.journal && !records.is_empty() {
+        write_journal(input, output, &records)?;
+    }
 
     log_patch_done(output, log_style);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func::fixtures::{fat, macho64, pe64};
+
+    fn patch(json: &str) -> Patch {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn output_offsets_follow_earlier_inserts() {
+        let mut data = vec![0; 32];
+        let patches = [
+            patch(r#"{"offset": "0x10", "hex_replace": "AA"}"#),
+            patch(r#"{"offset": "0x4", "hex_insert": "BB BB"}"#),
+            patch(r#"{"offset": "0x8", "hex_insert": "CC CC CC"}"#),
+        ];
+        let options = PatchOptions {
+            format: FileFormat::Raw,
+            ..PatchOptions::default()
+        };
+        let records = patch_data(&mut data, &patches, &[], &options, false).unwrap();
+        let offsets = output_offsets(&records);
+        assert_eq!(offsets, [0x15, 0x4, 0x8]);
+        for (record, offset) in records.iter().zip(offsets) {
+            assert_eq!(
+                data[offset..offset + record.new_bytes.len()],
+                record.new_bytes
+            );
+        }
+    }
+
+    #[test]
+    fn pe_checksum_is_updated_after_patching() {
+        let mut data = pe64(
+            0x1000,
+            0x1_8000_0000,
+            &[(".text", 0x1000, 0x400, 0x400, 0x400)],
+        );
+        let patches = [patch(r#"{"rva": "0x1010", "hex_replace": "90 90"}"#)];
+        let options = PatchOptions {
+            update_checksum: true,
+            ..PatchOptions::default()
+        };
+        let records = patch_data(&mut data, &patches, &[], &options, false).unwrap();
+        let pe = Pe::parse(&data).unwrap();
+        let checksum = pe.checksum(&data);
+        assert_ne!(checksum, 0);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].offset, pe.checksum_offset);
+        assert_eq!(records[1].new_bytes, checksum.to_le_bytes());
+        assert_eq!(&data[0x410..0x412], [0x90, 0x90]);
+
+        // A checksum that is already right is left alone
+        let records = patch_data(&mut data, &patches, &[], &options, false).unwrap();
+        assert_eq!(records.len(), 1);
+    }
+
+    #[test]
+    fn disassembly_of_a_site_past_the_end_is_skipped() {
+        let mut data = vec![0; 32];
+        let patches = [patch(r#"{"offset": "0x100", "hex_replace": "90"}"#)];
+        let options = PatchOptions {
+            format: FileFormat::Raw,
+            arch: Some("x86_64".into()),
+            disasm: true,
+            ..PatchOptions::default()
+        };
+        let records = patch_data(&mut data, &patches, &[], &options, false).unwrap();
+        assert!(records.is_empty());
+        assert_eq!(data, [0; 32]);
+    }
+
+    #[test]
+    fn universal_binary_offsets_are_relative_to_the_slice() {
+        let segments = [("__TEXT", 0x1_0000_0000, 0x1000, 0, 0x1000)];
+        let x86 = macho64(0x1000, 0x0100_0007, &segments);
+        let arm = macho64(0x1000, 0x0100_000C, &segments);
+        let original = fat(&[(0x0100_0007, &x86), (0x0100_000C, &arm)]);
+        let patches = [
+            patch(r#"{"offset": "0x800", "hex_replace": "AA"}"#),
+            patch(r#"{"rva": "0x900", "hex_replace": "BB"}"#),
+        ];
+        let options = PatchOptions {
+            arch: Some("arm64".into()),
+            ..PatchOptions::default()
+        };
+        let mut data = original.clone();
+        let records = patch_data(&mut data, &patches, &[], &options, false).unwrap();
+        let offsets: Vec<usize> = records.iter().map(|record| record.offset).collect();
+        assert_eq!(offsets, [0x2800, 0x2900]);
+        assert_eq!((data[0x2800], data[0x2900]), (0xAA, 0xBB));
+        // The other slice and the fat header are left alone
+        assert_eq!(data[..0x2000], original[..0x2000]);
+
+        let insert = [patch(r#"{"offset": "0x800", "hex_insert": "AA"}"#)];
+        let error = patch_data(&mut data, &insert, &[], &options, false).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("not supported inside a universal binary"));
+    }
+
+    #[test]
+    fn occurrences_pick_their_matches() {
+        let hits = || vec![0x10, 0x20, 0x30];
+        assert_eq!(
+            select_occurrences(hits(), Occurrence::First),
+            Ok(vec![0x10])
+        );
+        assert_eq!(select_occurrences(hits(), Occurrence::Last), Ok(vec![0x30]));
+        assert_eq!(select_occurrences(hits(), Occurrence::All), Ok(hits()));
+        assert_eq!(
+            select_occurrences(hits(), Occurrence::Nth(1)),
+            Ok(vec![0x10])
+        );
+        assert_eq!(
+            select_occurrences(hits(), Occurrence::Nth(2)),
+            Ok(vec![0x20])
+        );
+        assert_eq!(
+            select_occurrences(hits(), Occurrence::Nth(3)),
+            Ok(vec![0x30])
+        );
+        assert_eq!(
+            select_occurrences(hits(), Occurrence::Exactly(3)),
+            Ok(hits())
+        );
+
+        let error = select_occurrences(hits(), Occurrence::Nth(4)).unwrap_err();
+        assert!(error.contains("Match 4 requested but only 3 found"));
+        let error = select_occurrences(hits(), Occurrence::Exactly(2)).unwrap_err();
+        assert!(error.contains("exactly 2 matches for wildcard, found 3"));
+    }
+
+    #[test]
+    fn occurrences_without_matches() {
+        for occurrence in [Occurrence::First, Occurrence::Last, Occurrence::All] {
+            assert_eq!(
+                select_occurrences(Vec::<usize>::new(), occurrence),
+                Ok(vec![])
+            );
+        }
+        assert!(select_occurrences(Vec::<usize>::new(), Occurrence::Nth(1)).is_err());
+        assert_eq!(
+            select_occurrences(Vec::<usize>::new(), Occurrence::Exactly(0)),
+            Ok(vec![])
+        );
+    }
+
+    #[test]
+    fn wildcard_occurrences_are_patched() {
+        let original = [0xAB, 0xCD, 0x00, 0xAB, 0xCD, 0x00, 0xAB, 0xCD];
+        let options = PatchOptions {
+            format: FileFormat::Raw,
+            ..PatchOptions::default()
+        };
+        let patched = |json: &str| {
+            let mut data = original.to_vec();
+            patch_data(&mut data, &[patch(json)], &[], &options, false).map(|_| data)
+        };
+        assert_eq!(
+            patched(r#"{"wildcard": "AB CD", "occurrence": "all", "hex_replace": "90"}"#).unwrap(),
+            [0x90, 0xCD, 0x00, 0x90, 0xCD, 0x00, 0x90, 0xCD]
+        );
+        assert_eq!(
+            patched(r#"{"wildcard": "AB CD", "occurrence": {"nth": 2}, "hex_replace": "90"}"#)
+                .unwrap(),
+            [0xAB, 0xCD, 0x00, 0x90, 0xCD, 0x00, 0xAB, 0xCD]
+        );
+        assert_eq!(
+            patched(r#"{"wildcard": "AB CD", "occurrence": "last", "hex_replace": "90"}"#).unwrap(),
+            [0xAB, 0xCD, 0x00, 0xAB, 0xCD, 0x00, 0x90, 0xCD]
+        );
+        // A count that does not match skips the patch
+        assert_eq!(
+            patched(r#"{"wildcard": "AB CD", "occurrence": {"exactly": 2}, "hex_replace": "90"}"#)
+                .unwrap(),
+            original
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use crate::cli::patch::{patch_data, read_input, write_output, PatchOptions};
+use crate::func::archive::{disk_path, member_path};
+use crate::func::atomic::write_atomic;
+use crate::func::backup::create_backup;
+use crate::func::config::{BackupPolicy, Patch};
+use crate::func::logger::*;
+use crate::func::patchfile::{apply, create, PatchFormat};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Runs every patch against an in-memory copy of `input` and saves the difference between
+/// the original and patched bytes as an IPS, UPS or BPS file, chosen by `patch_file`'s extension.
+pub fn export_code(
+    input: &str,
+    patches: &[Patch],
+    symbols: &[String],
+    options: &PatchOptions,
+    patch_file: &Path,
+    log_style: bool,
+) -> Result<(), io::Error> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let format = PatchFormat::from_path(patch_file).map_err(invalid)?;
+
+    let original = read_input(input)?;
+    let mut data = original.clone();
+    let records = patch_data(&mut data, patches, symbols, options, log_style)?;
+    if records.is_empty() {
+        log_patch_skip(input, "No changes to export", log_style);
+        return Ok(());
+    }
+
+    let patch = create(format, &original, &data).map_err(invalid)?;
+    write_atomic(patch_file, &patch, None, false)?;
+    log_patchfile_exported(
+        &patch_file.display().to_string(),
+        &format.to_string(),
+        records.len(),
+        log_style,
+    );
+    Ok(())
+}
+
+/// Applies an IPS, UPS or BPS file to `input` and writes the result to `output`, backing up
+/// `input` first when patching in place.
+pub fn apply_patchfile_code(
+    patch_file: &Path,
+    input: &str,
+    output: &str,
+    backup: &BackupPolicy,
+    log_style: bool,
+) -> Result<(), io::Error> {
+    let invalid = |e: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", patch_file.display(), e),
+        )
+    };
+    let patch = fs::read(patch_file)?;
+    let format = PatchFormat::detect(&patch).map_err(invalid)?;
+    let data = read_input(input)?;
+    let patched = apply(&patch, &data).map_err(invalid)?;
+
+    let output_file = disk_path(output);
+    if disk_path(input) == output_file {
+        if let Some(backup) = create_backup(Path::new(output_file), backup)? {
+            log_backup_created(&backup.display().to_string(), log_style);
+        }
+    }
+    write_output(input, output, &patched, false)?;
+    if let Some(member) = member_path(output) {
+        log_archive_unsigned(member.archive, log_style);
+    }
+
+    log_patchfile_applied(
+        &patch_file.display().to_string(),
+        &format.to_string(),
+        log_style,
+    );
+    log_patch_done(output, log_style);
+    Ok(())
+}
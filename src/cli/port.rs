@@ -0,0 +1,306 @@
+use crate::cli::patch::read_input;
+use crate::func::atomic::write_atomic;
+use crate::func::binary::{select_slice, Binary, Isa};
+use crate::func::config::{FileEntry, Locator, Patch};
+use crate::func::header::read_config;
+use crate::func::logger::*;
+use crate::func::pattern::Pattern;
+use crate::func::port::{relocate, Relocation};
+use crate::func::scan::Scanner;
+use crate::func::signature::{unique_signature, MAX_SIGNATURE_LEN};
+use serde_json::{json, Map, Value};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+/// Fuzzy matches scoring below this are ported but flagged for review.
+const CONFIDENT_SCORE: f64 = 0.9;
+/// Keys that say where a patch applies, replaced when it is ported.
+const LOCATOR_KEYS: [&str; 5] = ["offset", "va", "rva", "wildcard", "position"];
+
+/// What happened to one patch.
+enum Outcome {
+    /// Left as it was.
+    Kept(String),
+    /// Moved to the new binary, replacing the locator with `fields`.
+    Ported {
+        fields: Map<String, Value>,
+        detail: String,
+        confident: bool,
+    },
+    /// Dropped from the ported config.
+    Failed(String),
+}
+
+/// One binary, narrowed to the slice the config patches.
+struct Image {
+    data: Vec<u8>,
+    binary: Binary,
+}
+
+impl Image {
+    fn load(path: &str, file: &FileEntry) -> Result<Self, io::Error> {
+        let invalid =
+            |e: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e));
+        let data = read_input(path)?;
+        let (range, _) = select_slice(&data, file.arch.as_deref()).map_err(invalid)?;
+        let data = data[range].to_vec();
+        let binary = Binary::load(&data, file.format).map_err(invalid)?;
+        Ok(Image { data, binary })
+    }
+
+    /// Starts of the matches of a `wildcard` patch, inside its `section` if it has one.
+    fn wildcard_hits(&self, pattern: &Pattern, patch: &Patch) -> Result<Vec<usize>, String> {
+        let mut hits = Scanner::new(&self.data, vec![pattern.clone()]).matches(0, &self.data);
+        if let Some(name) = &patch.section {
+            let range = self.binary.section_range(name)?;
+            hits.retain(|&(offset, len)| offset >= range.start && offset + len <= range.end);
+        }
+        Ok(hits.into_iter().map(|(offset, _)| offset).collect())
+    }
+}
+
+/// Moves every patch of config entry `index` from the `old` binary to the `new` one and writes
+/// the result to `output` as a copy of the config at `config_path`, plus a `report` listing what
+/// was ported, what needs review and what could not be found.
+pub fn port_code(
+    config_path: &Path,
+    index: usize,
+    old: &str,
+    new: &str,
+    output: &Path,
+    report: &Path,
+    log_style: bool,
+) -> Result<(), io::Error> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let files = read_config(config_path)
+        .map_err(|e| invalid(e.to_string()))?
+        .hexsaly
+        .files;
+    let file = files
+        .get(index)
+        .ok_or_else(|| invalid(format!("config has no file entry {}", index)))?;
+    let old_image = Image::load(old, file)?;
+    let new_image = Image::load(new, file)?;
+    let isa = old_image.binary.code_isa(file.arch.as_deref());
+
+    let outcomes: Vec<Outcome> = file
+        .patches
+        .iter()
+        .map(|patch| port_patch(patch, &old_image, &new_image, isa))
+        .collect();
+
+    // The rest of the config is copied as written
+    let reader = BufReader::new(File::open(config_path)?);
+    let mut config: Value = serde_json::from_reader(reader).map_err(|e| invalid(e.to_string()))?;
+    let entry = config
+        .pointer_mut(&format!("/Hexsaly/files/{}", index))
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| invalid(format!("config has no file entry {}", index)))?;
+    let in_place = entry
+        .get("output")
+        .is_none_or(|output| output == &entry["input"]);
+    entry.insert("input".into(), json!(new));
+    if in_place && entry.contains_key("output") {
+        entry.insert("output".into(), json!(new));
+    }
+    let patches = entry
+        .get_mut("patches")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| invalid(format!("file entry {} has no patches", index)))?;
+    let mut ported = Vec::new();
+    for (mut patch, outcome) in patches.drain(..).zip(&outcomes) {
+        match outcome {
+            Outcome::Kept(_) => ported.push(patch),
+            Outcome::Ported { fields, .. } => {
+                if let Some(patch_fields) = patch.as_object_mut() {
+                    patch_fields.retain(|key, _| !LOCATOR_KEYS.contains(&key.as_str()));
+                    patch_fields.extend(fields.clone());
+                }
+                ported.push(patch);
+            }
+            Outcome::Failed(_) => {}
+        }
+    }
+    *patches = ported;
+
+    let mut lines = vec![
+        "Hexsaly port report".to_string(),
+        format!("Old: {}", old),
+        format!("New: {}", new),
+        String::new(),
+    ];
+    let (mut ok, mut review, mut failed) = (0, 0, 0);
+    for (i, (patch, outcome)) in file.patches.iter().zip(&outcomes).enumerate() {
+        let (tag, detail) = match outcome {
+            Outcome::Kept(reason) => ("KEPT", reason.clone()),
+            Outcome::Ported {
+                detail, confident, ..
+            } => (if *confident { "PORTED" } else { "REVIEW" }, detail.clone()),
+            Outcome::Failed(reason) => ("FAILED", reason.clone()),
+        };
+        match tag {
+            "REVIEW" => review += 1,
+            "FAILED" => failed += 1,
+            _ => ok += 1,
+        }
+        log_port_result(tag, i, &patch.locator.to_string(), &detail, log_style);
+        lines.push(format!("[{}] #{} {}: {}", tag, i, patch.locator, detail));
+    }
+    lines.insert(
+        3,
+        format!(
+            "{} ported or kept, {} to review, {} not ported",
+            ok, review, failed
+        ),
+    );
+
+    let json = serde_json::to_string_pretty(&config).map_err(|e| invalid(e.to_string()))?;
+    write_atomic(output, json.as_bytes(), None, false)?;
+    write_atomic(report, (lines.join("\n") + "\n").as_bytes(), None, false)?;
+    log_port_done(
+        &output.display().to_string(),
+        &report.display().to_string(),
+        review + failed,
+        log_style,
+    );
+    Ok(())
+}
+
+/// A patch located in the new binary.
+struct Moved {
+    /// Locator keys for the ported patch.
+    fields: Map<String, Value>,
+    /// Where the patch writes.
+    site: usize,
+    /// Where the patch's `expect` is checked: the site, or the start of a wildcard's match.
+    expect_at: usize,
+    detail: String,
+    confident: bool,
+}
+
+fn port_patch(patch: &Patch, old: &Image, new: &Image, isa: Isa) -> Outcome {
+    let len = |site: usize| -> Result<usize, String> {
+        let original = old.data.get(site..).unwrap_or_default();
+        Ok(patch.action.payload().encode(original, site, isa)?.len())
+    };
+    let moved = match &patch.locator {
+        Locator::MethodName(_) | Locator::StringLiteral(_) => {
+            return Outcome::Kept(
+                "resolved from the symbol sources, which must match the new binary".into(),
+            )
+        }
+        Locator::Offset(offset) => len(*offset).and_then(|len| {
+            let moved = relocate_site(old, new, *offset, len, isa)?;
+            let offset = format!("0x{:X}", moved.site);
+            Ok(moved.locate("offset", offset))
+        }),
+        Locator::Va(va) => old.binary.va_to_offset(*va).and_then(|site| {
+            let moved = relocate_site(old, new, site, len(site)?, isa)?;
+            let va = new.binary.offset_to_va(moved.site)?;
+            Ok(moved.locate("va", format!("0x{:X}", va)))
+        }),
+        Locator::Rva(rva) => old.binary.rva_to_offset(*rva).and_then(|site| {
+            let moved = relocate_site(old, new, site, len(site)?, isa)?;
+            let rva = new.binary.offset_to_rva(moved.site)?;
+            Ok(moved.locate("rva", format!("0x{:X}", rva)))
+        }),
+        Locator::Wildcard(pattern) => match port_wildcard(pattern, patch, old, new, isa) {
+            Ok(None) => return Outcome::Kept("wildcard still matches".into()),
+            Ok(Some(moved)) => Ok(moved),
+            Err(e) => Err(e),
+        },
+    };
+
+    let mut moved = match moved {
+        Ok(moved) => moved,
+        Err(e) => return Outcome::Failed(e),
+    };
+    if patch
+        .expect
+        .as_ref()
+        .is_some_and(|expect| !expect.matches_at(&new.data, moved.expect_at))
+    {
+        moved.detail.push_str(", expected bytes differ");
+        moved.confident = false;
+    }
+    Outcome::Ported {
+        fields: moved.fields,
+        detail: format!("-> 0x{:X} ({})", moved.site, moved.detail),
+        confident: moved.confident,
+    }
+}
+
+impl Moved {
+    fn locate(mut self, key: &str, value: String) -> Self {
+        self.fields.insert(key.into(), json!(value));
+        self
+    }
+}
+
+/// Where a site moved to, and how it was found along with whether that is trustworthy.
+fn relocate_site(
+    old: &Image,
+    new: &Image,
+    site: usize,
+    len: usize,
+    isa: Isa,
+) -> Result<Moved, String> {
+    let Relocation { offset, score } = relocate(&old.data, &new.data, site, len, isa)?;
+    let (detail, confident) = match score {
+        None => ("exact".to_string(), true),
+        Some(score) => (
+            format!("fuzzy, {:.0}% similar", score * 100.0),
+            score >= CONFIDENT_SCORE,
+        ),
+    };
+    Ok(Moved {
+        fields: Map::new(),
+        site: offset,
+        expect_at: offset,
+        detail,
+        confident,
+    })
+}
+
+/// `None` when the wildcard matches the new binary as often as the old one, otherwise a new
+/// signature for the place its single old match moved to.
+fn port_wildcard(
+    pattern: &Pattern,
+    patch: &Patch,
+    old: &Image,
+    new: &Image,
+    isa: Isa,
+) -> Result<Option<Moved>, String> {
+    let old_hits = old.wildcard_hits(pattern, patch)?;
+    let new_hits = new.wildcard_hits(pattern, patch)?;
+    if !old_hits.is_empty() && old_hits.len() == new_hits.len() {
+        return Ok(None);
+    }
+    let [start] = old_hits[..] else {
+        return Err(format!(
+            "wildcard matches {} times in the old binary and {} in the new one",
+            old_hits.len(),
+            new_hits.len()
+        ));
+    };
+
+    // The match start moves, and the patch keeps writing `position` bytes after it
+    let position = patch.position.unwrap_or(0);
+    let site = start + position;
+    let original = old.data.get(site..).unwrap_or_default();
+    let payload = patch.action.payload().encode(original, site, isa)?;
+    let len = pattern.max_len().max(position + payload.len());
+    let mut moved = relocate_site(old, new, start, len, isa)?;
+    let signature_len = len.min(MAX_SIGNATURE_LEN);
+    let signature = unique_signature(&new.data, moved.site, signature_len, MAX_SIGNATURE_LEN, isa)?;
+    moved.expect_at = moved.site - signature.position;
+    moved.site += position;
+    moved = moved.locate("wildcard", signature.pattern);
+    if signature.position + position != 0 {
+        moved
+            .fields
+            .insert("position".into(), json!(signature.position + position));
+    }
+    Ok(Some(moved))
+}
@@ -0,0 +1,457 @@
+use crate::cli::patch::{
+    find_offset_by_method_name, find_offset_by_string_literal, read_input, write_output,
+    PatchOptions, PatchRecord,
+};
+use crate::func::archive::member_path;
+use crate::func::atomic::write_atomic;
+use crate::func::binary::{select_slice, Binary, Isa};
+use crate::func::config::{Action, AddressKind, Locator, Patch};
+use crate::func::dump::{CacheMode, Dump};
+use crate::func::hex::{format_bytes, parse_bytes, parse_offset};
+use crate::func::logger::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::Path;
+
+/// Undo journal written next to a patched file, listing every change in the order it was made.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Journal {
+    pub input: String,
+    pub output: String,
+    pub records: Vec<JournalRecord>,
+}
+
+/// One change in the order it was made. Its offset is where it was written at the time, before
+/// any later insert shifted it, so undoing the records in reverse finds each one in place.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JournalRecord {
+    pub offset: String,
+    pub old: String,
+    pub new: String,
+    #[serde(default)]
+    pub inserted: bool,
+}
+
+impl From<&PatchRecord> for JournalRecord {
+    fn from(record: &PatchRecord) -> Self {
+        JournalRecord {
+            offset: format!("0x{:X}", record.offset),
+            old: format_bytes(&record.old_bytes),
+            new: format_bytes(&record.new_bytes),
+            inserted: record.inserted,
+        }
+    }
+}
+
+impl TryFrom<&JournalRecord> for PatchRecord {
+    type Error = String;
+
+    fn try_from(record: &JournalRecord) -> Result<Self, Self::Error> {
+        Ok(PatchRecord {
+            offset: parse_offset(&record.offset)?,
+            old_bytes: parse_bytes(&record.old)?,
+            new_bytes: parse_bytes(&record.new)?,
+            inserted: record.inserted,
+        })
+    }
+}
+
+/// Journal file for `output`. For an archive member it sits next to the archive, named after
+/// the member: `game.apk!lib/x86/libgame.so` uses `game.apk.lib_x86_libgame.so.hexsaly-undo.json`.
+pub fn journal_path(output: &str) -> String {
+    match member_path(output) {
+        Some(member) => format!(
+            "{}.{}.hexsaly-undo.json",
+            member.archive,
+            member.members.join("!").replace(['/', '\\', '!'], "_")
+        ),
+        None => format!("{}.hexsaly-undo.json", output),
+    }
+}
+
+pub fn read_journal(path: &str) -> Result<Journal, io::Error> {
+    let reader = BufReader::new(File::open(path)?);
+    serde_json::from_reader(reader).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid undo journal '{}': {}", path, e),
+        )
+    })
+}
+
+/// Writes the undo journal for `output`. When a file is patched in place again, the new
+/// records are appended so a single revert walks back every run.
+pub fn write_journal(input: &str, output: &str, records: &[PatchRecord]) -> Result<(), io::Error> {
+    let path = journal_path(output);
+    let mut journal = match read_journal(&path) {
+        Ok(existing) if input == output => existing,
+        _ => Journal {
+            input: input.to_string(),
+            output: output.to_string(),
+            records: Vec::new(),
+        },
+    };
+    journal
+        .records
+        .extend(records.iter().map(JournalRecord::from));
+
+    let json = serde_json::to_string_pretty(&journal)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_atomic(Path::new(&path), json.as_bytes(), None, false)
+}
+
+/// Undoes `records` in reverse order, refusing to touch `data` unless every site still holds
+/// exactly the bytes that were written there.
+pub fn undo_records(
+    data: &mut Vec<u8>,
+    records: &[PatchRecord],
+    log_style: bool,
+) -> Result<(), String> {
+    let mut reverted = data.clone();
+
+    for record in records.iter().rev() {
+        let end = record.offset + record.new_bytes.len();
+        if reverted.get(record.offset..end) != Some(record.new_bytes.as_slice()) {
+            return Err(format!(
+                "Bytes at offset 0x{:X} do not match what was written ({}), refusing to revert",
+                record.offset,
+                format_bytes(&record.new_bytes)
+            ));
+        }
+
+        if record.inserted {
+            reverted.drain(record.offset..end);
+        } else {
+            if record.old_bytes.len() != record.new_bytes.len() {
+                return Err(format!(
+                    "Record at offset 0x{:X} has mismatched old and new lengths",
+                    record.offset
+                ));
+            }
+            reverted[record.offset..end].copy_from_slice(&record.old_bytes);
+        }
+    }
+
+    // Only report once every record has been verified
+    for record in records.iter().rev() {
+        if record.inserted {
+            log_offset(record.offset, log_style, "Removing");
+            log_patch_action("Removed", &format_bytes(&record.new_bytes), log_style);
+        } else {
+            log_offset(record.offset, log_style, "Restoring");
+            log_patch_action("Restored", &format_bytes(&record.old_bytes), log_style);
+        }
+    }
+
+    *data = reverted;
+    Ok(())
+}
+
+/// Rebuilds undo records from a config's patches, using each patch's literal `expect` bytes as
+/// the original content. Wildcard patches cannot be located once patched and are refused, and
+/// assembly is encoded for `isa` at its site as it was when patching.
+pub fn records_from_patches(
+    patches: &[Patch],
+    binary: &Binary,
+    dump: &Dump,
+    method_address: AddressKind,
+    isa: Isa,
+    log_style: bool,
+) -> Result<Vec<PatchRecord>, io::Error> {
+    let invalid = |item: &str, reason: &str| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", item, reason))
+    };
+
+    let mut records = Vec::new();
+    for patch in patches {
+        let item = patch.locator.to_string();
+        let offset = match &patch.locator {
+            Locator::Offset(offset) => *offset,
+            Locator::Va(address) => binary
+                .va_to_offset(*address)
+                .map_err(|e| invalid(&item, &e))?,
+            Locator::Rva(address) => binary
+                .rva_to_offset(*address)
+                .map_err(|e| invalid(&item, &e))?,
+            Locator::MethodName(method_name) => {
+                let kind = patch.address.unwrap_or(method_address);
+                find_offset_by_method_name(method_name, dump, binary, kind, log_style)
+                    .ok_or_else(|| invalid(&item, "Method not found"))?
+            }
+            Locator::StringLiteral(value) => {
+                find_offset_by_string_literal(value, dump, binary, log_style)
+                    .ok_or_else(|| invalid(&item, "String literal not found"))?
+            }
+            Locator::Wildcard(_) => {
+                return Err(invalid(
+                    &item,
+                    "wildcard patches cannot be reverted without an undo journal",
+                ))
+            }
+        };
+
+        let encode = |original: &[u8]| {
+            patch
+                .action
+                .payload()
+                .encode(original, offset, isa)
+                .map_err(|e| invalid(&item, &e))
+        };
+        let record = match &patch.action {
+            Action::Insert(_) => PatchRecord {
+                offset,
+                old_bytes: Vec::new(),
+                new_bytes: encode(&[])?,
+                inserted: true,
+            },
+            Action::Replace(_) => {
+                let uncovered = || {
+                    invalid(
+                        &item,
+                        "reverting requires an `expect` without `??` covering every written byte",
+                    )
+                };
+                let original = patch
+                    .expect
+                    .as_ref()
+                    .and_then(|expect| expect.literal_bytes())
+                    .ok_or_else(uncovered)?;
+                let bytes = encode(&original)?;
+                if original.len() < bytes.len() {
+                    return Err(uncovered());
+                }
+                let mut new_bytes = original.clone();
+                new_bytes[..bytes.len()].copy_from_slice(&bytes);
+                PatchRecord {
+                    offset,
+                    old_bytes: original,
+                    new_bytes,
+                    inserted: false,
+                }
+            }
+        };
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn write_reverted(
+    target: &str,
+    output: &str,
+    data: &[u8],
+    log_style: bool,
+) -> Result<(), io::Error> {
+    write_output(target, output, data, false)?;
+    if let Some(member) = member_path(output) {
+        log_archive_unsigned(member.archive, log_style);
+    }
+    log_patch_done(output, log_style);
+    Ok(())
+}
+
+/// Restores `target` to its pre-patch state using its undo journal.
+pub fn revert_code(
+    target: &str,
+    output: &str,
+    journal: Option<&str>,
+    log_style: bool,
+) -> Result<(), io::Error> {
+    let default_journal = journal_path(target);
+    let journal_file = journal.unwrap_or(&default_journal);
+    if !Path::new(journal_file).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Undo journal '{}' does not exist", journal_file),
+        ));
+    }
+
+    let records = read_journal(journal_file)?
+        .records
+        .iter()
+        .map(PatchRecord::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut data = read_input(target)?;
+    undo_records(&mut data, &records, log_style)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_reverted(target, output, &data, log_style)?;
+
+    // The journal no longer describes the file once it has been reverted in place
+    if target == output {
+        fs::remove_file(journal_file)?;
+    }
+    Ok(())
+}
+
+/// Restores `target` using the `expect` bytes of the patches that were applied to it.
+pub fn revert_with_patches(
+    target: &str,
+    output: &str,
+    patches: &[Patch],
+    symbols: &[String],
+    options: &PatchOptions,
+    log_style: bool,
+) -> Result<(), io::Error> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let needs_symbols = patches.iter().any(|patch| {
+        matches!(
+            patch.locator,
+            Locator::MethodName(_) | Locator::StringLiteral(_)
+        )
+    });
+    if needs_symbols && symbols.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "`dump_cs` or `symbols` is required for method_name and string_literal patches",
+        ));
+    }
+    let dump = if needs_symbols {
+        let mode = if options.symbol_cache {
+            CacheMode::ReadWrite
+        } else {
+            CacheMode::Off
+        };
+        Dump::load(symbols, mode)?
+    } else {
+        Dump::default()
+    };
+
+    let mut data = read_input(target)?;
+    let (range, _) = select_slice(&data, options.arch.as_deref()).map_err(invalid)?;
+    let binary = Binary::load(&data[range.clone()], options.format).map_err(invalid)?;
+    let isa = binary.code_isa(options.arch.as_deref());
+    let mut records = records_from_patches(
+        patches,
+        &binary,
+        &dump,
+        options.method_address,
+        isa,
+        log_style,
+    )?;
+    for record in &mut records {
+        record.offset += range.start;
+    }
+    undo_records(&mut data, &records, log_style)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_reverted(target, output, &data, log_style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::patch::patch_data;
+    use crate::func::config::FileFormat;
+    use crate::func::fixtures::{distinct, scratch_dir};
+
+    fn patch_raw(data: &mut Vec<u8>, patches: &[&str]) -> Vec<PatchRecord> {
+        let patches: Vec<Patch> = patches
+            .iter()
+            .map(|json| serde_json::from_str(json).unwrap())
+            .collect();
+        let options = PatchOptions {
+            format: FileFormat::Raw,
+            ..PatchOptions::default()
+        };
+        patch_data(data, &patches, &[], &options, false).unwrap()
+    }
+
+    #[test]
+    fn undoing_restores_the_original() {
+        let original = distinct(64);
+        let mut data = original.clone();
+        let records = patch_raw(
+            &mut data,
+            &[
+                r#"{"offset": "0x10", "hex_replace": "AA BB"}"#,
+                r#"{"offset": "0x4", "hex_insert": "CC CC CC"}"#,
+                r#"{"offset": "0x5", "hex_replace": "DD"}"#,
+                r#"{"offset": "0x20", "hex_insert": "EE"}"#,
+            ],
+        );
+        assert_eq!(data.len(), original.len() + 4);
+        undo_records(&mut data, &records, false).unwrap();
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn changed_sites_are_not_reverted() {
+        let original = distinct(64);
+        let mut data = original.clone();
+        let records = patch_raw(
+            &mut data,
+            &[
+                r#"{"offset": "0x10", "hex_replace": "AA BB"}"#,
+                r#"{"offset": "0x30", "hex_replace": "CC"}"#,
+            ],
+        );
+        data[0x11] = 0;
+        let patched = data.clone();
+        let error = undo_records(&mut data, &records, false).unwrap_err();
+        assert!(error.contains("offset 0x10"), "{}", error);
+        // Nothing is undone, not even the record that still matches
+        assert_eq!(data, patched);
+
+        let mut short = patched[..0x30].to_vec();
+        assert!(undo_records(&mut short, &records[1..], false).is_err());
+    }
+
+    #[test]
+    fn replacements_of_another_length_are_refused() {
+        let mut data = vec![0xAA; 8];
+        let records = [PatchRecord {
+            offset: 2,
+            old_bytes: vec![0],
+            new_bytes: vec![0xAA, 0xAA],
+            inserted: false,
+        }];
+        let error = undo_records(&mut data, &records, false).unwrap_err();
+        assert!(error.contains("mismatched old and new lengths"));
+        assert_eq!(data, [0xAA; 8]);
+    }
+
+    #[test]
+    fn journals_append_runs_in_place() {
+        let dir = scratch_dir("journal");
+        let path = dir.join("game.bin").to_string_lossy().into_owned();
+        let original = distinct(64);
+        let mut data = original.clone();
+        let first = patch_raw(&mut data, &[r#"{"offset": "0x8", "hex_insert": "AA BB"}"#]);
+        write_journal(&path, &path, &first).unwrap();
+        let second = patch_raw(&mut data, &[r#"{"offset": "0x9", "hex_replace": "CC DD"}"#]);
+        write_journal(&path, &path, &second).unwrap();
+
+        let journal = read_journal(&journal_path(&path)).unwrap();
+        assert_eq!(journal.records.len(), 2);
+        assert_eq!(journal.records[0].offset, "0x8");
+        assert!(journal.records[0].inserted);
+        let records: Vec<PatchRecord> = journal
+            .records
+            .iter()
+            .map(|record| PatchRecord::try_from(record).unwrap())
+            .collect();
+        undo_records(&mut data, &records, false).unwrap();
+        assert_eq!(data, original);
+
+        // Writing to another output starts a new journal
+        let output = dir.join("game.patched.bin").to_string_lossy().into_owned();
+        write_journal(&path, &output, &second).unwrap();
+        write_journal(&path, &output, &second).unwrap();
+        assert_eq!(
+            read_journal(&journal_path(&output)).unwrap().records.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn member_journals_sit_next_to_the_archive() {
+        assert_eq!(
+            journal_path("game.apk!lib/arm64-v8a/libgame.so"),
+            "game.apk.lib_arm64-v8a_libgame.so.hexsaly-undo.json"
+        );
+        assert_eq!(journal_path("game.bin"), "game.bin.hexsaly-undo.json");
+    }
+}
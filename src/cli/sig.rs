@@ -0,0 +1,62 @@
+use crate::cli::patch::{find_offset_by_method_name, read_input};
+use crate::func::binary::{select_slice, Binary};
+use crate::func::config::{AddressKind, FileFormat};
+use crate::func::dump::{CacheMode, Dump};
+use crate::func::hex::parse_offset;
+use crate::func::logger::*;
+use crate::func::signature::{unique_signature, MAX_SIGNATURE_LEN};
+use std::io;
+
+/// Where `sig` builds its signature.
+#[derive(Debug, Clone)]
+pub enum SigTarget {
+    Offset(String),
+    MethodName(String),
+}
+
+/// Prints a signature that matches only the given offset or method in `input`, together with
+/// the `position` a `wildcard` patch needs to write at that site. In a universal binary the
+/// offset is relative to the `arch` slice, as it is in a config.
+pub fn sig_code(
+    input: &str,
+    target: &SigTarget,
+    symbols: &[String],
+    arch: Option<&str>,
+    min_len: usize,
+    log_style: bool,
+) -> Result<(), io::Error> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    if min_len > MAX_SIGNATURE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("--len can be at most {} bytes", MAX_SIGNATURE_LEN),
+        ));
+    }
+    let data = read_input(input)?;
+    let (range, slice) = select_slice(&data, arch).map_err(invalid)?;
+    if let Some(slice) = &slice {
+        log_slice_selected(slice, range.start, range.len(), log_style);
+    }
+    let data = &data[range];
+    let binary = Binary::load(data, FileFormat::Auto).unwrap_or(Binary::Raw);
+
+    let offset = match target {
+        SigTarget::Offset(offset) => parse_offset(offset).map_err(invalid)?,
+        SigTarget::MethodName(name) => {
+            if symbols.is_empty() {
+                return Err(invalid(
+                    "a method name needs symbols; pass dump.cs with --dump".into(),
+                ));
+            }
+            let dump = Dump::load(symbols, CacheMode::Off)?;
+            find_offset_by_method_name(name, &dump, &binary, AddressKind::Offset, log_style)
+                .ok_or_else(|| invalid(format!("cannot resolve method '{}'", name)))?
+        }
+    };
+
+    let isa = binary.code_isa(arch);
+    let signature =
+        unique_signature(data, offset, min_len, MAX_SIGNATURE_LEN, isa).map_err(invalid)?;
+    log_signature(&signature.pattern, signature.position, offset, log_style);
+    Ok(())
+}
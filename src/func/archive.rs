@@ -0,0 +1,520 @@
+use crate::func::binary::Reader;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::io::{Read, Write};
+use std::path::Path;
+
+const LOCAL_HEADER: u32 = 0x0403_4B50;
+const CENTRAL_HEADER: u32 = 0x0201_4B50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4B50;
+const DATA_DESCRIPTOR: u32 = 0x0807_4B50;
+const LOCAL_HEADER_SIZE: usize = 30;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+const FLAG_ENCRYPTED: u16 = 1;
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+
+/// Extra field used by Android's zipalign to pad the local header of a stored entry.
+const ALIGNMENT_EXTRA_ID: u16 = 0xD935;
+/// Uncompressed native libraries are mapped straight from the APK, so they need page alignment.
+const NATIVE_LIBRARY_ALIGNMENT: usize = 4096;
+const STORED_ALIGNMENT: usize = 4;
+
+/// A path into an archive such as `game.xapk!base.apk!lib/arm64-v8a/libil2cpp.so`: a file on
+/// disk followed by one member name per level of nesting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberPath<'a> {
+    pub archive: &'a str,
+    pub members: Vec<&'a str>,
+}
+
+/// Splits `path` at each `!`, unless it names an existing file or has no `!`.
+pub fn member_path(path: &str) -> Option<MemberPath<'_>> {
+    if !path.contains('!') || Path::new(path).exists() {
+        return None;
+    }
+    let mut parts = path.split('!');
+    let archive = parts.next()?;
+    let members: Vec<&str> = parts.collect();
+    if archive.is_empty() || members.iter().any(|member| member.is_empty()) {
+        return None;
+    }
+    Some(MemberPath { archive, members })
+}
+
+/// The file on disk that holds `path`: the archive for a member path, otherwise `path` itself.
+pub fn disk_path(path: &str) -> &str {
+    member_path(path).map_or(path, |member| member.archive)
+}
+
+/// Uncompressed contents of the member reached by following `members` through nested archives.
+pub fn read_member(archive: &[u8], members: &[&str]) -> Result<Vec<u8>, String> {
+    let (name, rest) = members
+        .split_first()
+        .ok_or_else(|| String::from("no archive member given"))?;
+    let zip = Zip::parse(archive)?;
+    let data = zip.read(zip.entry(name)?)?;
+    if rest.is_empty() {
+        Ok(data)
+    } else {
+        read_member(&data, rest).map_err(|e| format!("{}: {}", name, e))
+    }
+}
+
+/// A copy of `archive` with the member reached through `members` replaced by `data`.
+///
+/// Every other entry is copied byte for byte in its original order. The replaced entry keeps
+/// its compression method, and stored entries are aligned as zipalign would (4 KB for `.so`).
+/// Anything between the entries and the central directory, such as an APK signing block, is
+/// dropped because the signature no longer matches.
+pub fn replace_member(archive: &[u8], members: &[&str], data: &[u8]) -> Result<Vec<u8>, String> {
+    let (name, rest) = members
+        .split_first()
+        .ok_or_else(|| String::from("no archive member given"))?;
+    let zip = Zip::parse(archive)?;
+    let entry = zip.entry(name)?;
+    let replacement = if rest.is_empty() {
+        data.to_vec()
+    } else {
+        let inner = zip.read(entry)?;
+        replace_member(&inner, rest, data).map_err(|e| format!("{}: {}", name, e))?
+    };
+    zip.rebuild(name, &replacement)
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    name: String,
+    method: u16,
+    flags: u16,
+    compressed_size: usize,
+    size: usize,
+    crc: u32,
+    local_offset: usize,
+    /// The whole central directory record, including name, extra field and comment.
+    central: Vec<u8>,
+}
+
+struct Zip<'a> {
+    data: &'a [u8],
+    entries: Vec<Entry>,
+    comment: &'a [u8],
+}
+
+impl<'a> Zip<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, String> {
+        let r = Reader::new(data, false);
+        // The end record sits in the last 64 KB + 22 bytes, behind an optional comment
+        let search_start = data
+            .len()
+            .saturating_sub(END_OF_CENTRAL_DIRECTORY_SIZE + u16::MAX as usize);
+        let end = (search_start..=data.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_SIZE))
+            .rev()
+            .find(|&at| r.u32(at) == Ok(END_OF_CENTRAL_DIRECTORY))
+            .ok_or_else(|| String::from("not a ZIP archive"))?;
+
+        let count = r.u16(end + 10)? as usize;
+        let directory_offset = r.u32(end + 16)?;
+        let comment_len = r.u16(end + 20)? as usize;
+        if count == 0xFFFF || directory_offset == u32::MAX {
+            return Err("ZIP64 archives are not supported".into());
+        }
+        let comment = data
+            .get(end + 22..end + 22 + comment_len)
+            .ok_or_else(|| String::from("truncated archive comment"))?;
+
+        let mut entries = Vec::with_capacity(count);
+        let mut at = directory_offset as usize;
+        for _ in 0..count {
+            if r.u32(at)? != CENTRAL_HEADER {
+                return Err(format!("bad central directory entry at offset 0x{:X}", at));
+            }
+            let name_len = r.u16(at + 28)? as usize;
+            let extra_len = r.u16(at + 30)? as usize;
+            let comment_len = r.u16(at + 32)? as usize;
+            let record_end = at + CENTRAL_HEADER_SIZE + name_len + extra_len + comment_len;
+            let central = data
+                .get(at..record_end)
+                .ok_or_else(|| String::from("truncated central directory"))?;
+            let name_bytes = &central[CENTRAL_HEADER_SIZE..CENTRAL_HEADER_SIZE + name_len];
+
+            let compressed_size = r.u32(at + 20)?;
+            let size = r.u32(at + 24)?;
+            let local_offset = r.u32(at + 42)?;
+            if [compressed_size, size, local_offset].contains(&u32::MAX) {
+                return Err("ZIP64 archives are not supported".into());
+            }
+            entries.push(Entry {
+                name: String::from_utf8_lossy(name_bytes).into_owned(),
+                method: r.u16(at + 10)?,
+                flags: r.u16(at + 8)?,
+                compressed_size: compressed_size as usize,
+                size: size as usize,
+                crc: r.u32(at + 16)?,
+                local_offset: local_offset as usize,
+                central: central.to_vec(),
+            });
+            at = record_end;
+        }
+
+        Ok(Zip {
+            data,
+            entries,
+            comment,
+        })
+    }
+
+    fn entry(&self, name: &str) -> Result<&Entry, String> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| format!("no member '{}' in archive", name))
+    }
+
+    /// `(header end, data end)` of an entry's local record.
+    fn local_layout(&self, entry: &Entry) -> Result<(usize, usize), String> {
+        let r = Reader::new(self.data, false);
+        let at = entry.local_offset;
+        if r.u32(at)? != LOCAL_HEADER {
+            return Err(format!("bad local header for '{}'", entry.name));
+        }
+        let header_end =
+            at + LOCAL_HEADER_SIZE + r.u16(at + 26)? as usize + r.u16(at + 28)? as usize;
+        let data_end = header_end + entry.compressed_size;
+        if data_end > self.data.len() {
+            return Err(format!(
+                "'{}' extends past the end of the archive",
+                entry.name
+            ));
+        }
+        Ok((header_end, data_end))
+    }
+
+    fn read(&self, entry: &Entry) -> Result<Vec<u8>, String> {
+        if entry.flags & FLAG_ENCRYPTED != 0 {
+            return Err(format!("'{}' is encrypted", entry.name));
+        }
+        let (start, end) = self.local_layout(entry)?;
+        let raw = &self.data[start..end];
+        let data = match entry.method {
+            STORED => raw.to_vec(),
+            DEFLATED => {
+                let mut data = Vec::with_capacity(entry.size);
+                DeflateDecoder::new(raw)
+                    .read_to_end(&mut data)
+                    .map_err(|e| format!("cannot inflate '{}': {}", entry.name, e))?;
+                data
+            }
+            method => {
+                return Err(format!(
+                    "'{}' uses unsupported compression method {}",
+                    entry.name, method
+                ))
+            }
+        };
+        if crc32fast::hash(&data) != entry.crc {
+            return Err(format!("CRC mismatch in '{}'", entry.name));
+        }
+        Ok(data)
+    }
+
+    fn rebuild(&self, name: &str, replacement: &[u8]) -> Result<Vec<u8>, String> {
+        let r = Reader::new(self.data, false);
+        let mut out = Vec::with_capacity(self.data.len() + replacement.len());
+        let mut new_offsets = vec![0usize; self.entries.len()];
+
+        // Local records are written in their original physical order
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        order.sort_by_key(|&i| self.entries[i].local_offset);
+
+        let mut replaced = None;
+        for i in order {
+            let entry = &self.entries[i];
+            let at = entry.local_offset;
+            let (header_end, data_end) = self.local_layout(entry)?;
+            let name_len = r.u16(at + 26)? as usize;
+            let name_end = at + LOCAL_HEADER_SIZE + name_len;
+            let mut header = self.data[at..name_end].to_vec();
+            let mut extra = self.data[name_end..header_end].to_vec();
+
+            let (body, descriptor) = if entry.name == name {
+                let body = match entry.method {
+                    STORED => replacement.to_vec(),
+                    DEFLATED => deflate(replacement)
+                        .map_err(|e| format!("cannot deflate '{}': {}", entry.name, e))?,
+                    method => {
+                        return Err(format!(
+                            "'{}' uses unsupported compression method {}",
+                            entry.name, method
+                        ))
+                    }
+                };
+                let sizes = (
+                    crc32fast::hash(replacement),
+                    u32::try_from(body.len()),
+                    u32::try_from(replacement.len()),
+                );
+                let (crc, Ok(compressed_size), Ok(size)) = sizes else {
+                    return Err(format!("'{}' is too large for a ZIP archive", entry.name));
+                };
+                // Sizes go in the header, so no data descriptor follows
+                let flags = entry.flags & !FLAG_DATA_DESCRIPTOR;
+                header[6..8].copy_from_slice(&flags.to_le_bytes());
+                header[14..18].copy_from_slice(&crc.to_le_bytes());
+                header[18..22].copy_from_slice(&compressed_size.to_le_bytes());
+                header[22..26].copy_from_slice(&size.to_le_bytes());
+                replaced = Some((i, flags, crc, compressed_size, size));
+                (body, &[][..])
+            } else {
+                let descriptor_len = match entry.flags & FLAG_DATA_DESCRIPTOR {
+                    0 => 0,
+                    _ if r.u32(data_end) == Ok(DATA_DESCRIPTOR) => 16,
+                    _ => 12,
+                };
+                let descriptor = self
+                    .data
+                    .get(data_end..data_end + descriptor_len)
+                    .ok_or_else(|| format!("truncated data descriptor for '{}'", entry.name))?;
+                (self.data[header_end..data_end].to_vec(), descriptor)
+            };
+
+            if entry.method == STORED {
+                let alignment = if entry.name.ends_with(".so") {
+                    NATIVE_LIBRARY_ALIGNMENT
+                } else {
+                    STORED_ALIGNMENT
+                };
+                extra = aligned_extra(&extra, out.len() + header.len(), alignment);
+            }
+            let extra_len = u16::try_from(extra.len())
+                .map_err(|_| format!("extra field of '{}' is too large", entry.name))?;
+            header[28..30].copy_from_slice(&extra_len.to_le_bytes());
+
+            new_offsets[i] = out.len();
+            out.extend_from_slice(&header);
+            out.extend_from_slice(&extra);
+            out.extend_from_slice(&body);
+            out.extend_from_slice(descriptor);
+        }
+
+        let directory_offset = out.len();
+        for (i, entry) in self.entries.iter().enumerate() {
+            let mut central = entry.central.clone();
+            let offset = u32::try_from(new_offsets[i])
+                .map_err(|_| String::from("archive is too large for ZIP"))?;
+            central[42..46].copy_from_slice(&offset.to_le_bytes());
+            if let Some((_, flags, crc, compressed_size, size)) =
+                replaced.filter(|&(index, ..)| index == i)
+            {
+                central[8..10].copy_from_slice(&flags.to_le_bytes());
+                central[16..20].copy_from_slice(&crc.to_le_bytes());
+                central[20..24].copy_from_slice(&compressed_size.to_le_bytes());
+                central[24..28].copy_from_slice(&size.to_le_bytes());
+            }
+            out.extend_from_slice(&central);
+        }
+        let directory_size = out.len() - directory_offset;
+        let (Ok(directory_offset), Ok(directory_size)) = (
+            u32::try_from(directory_offset),
+            u32::try_from(directory_size),
+        ) else {
+            return Err("archive is too large for ZIP".into());
+        };
+
+        let count = self.entries.len() as u16;
+        out.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&count.to_le_bytes());
+        out.extend_from_slice(&count.to_le_bytes());
+        out.extend_from_slice(&directory_size.to_le_bytes());
+        out.extend_from_slice(&directory_offset.to_le_bytes());
+        out.extend_from_slice(&(self.comment.len() as u16).to_le_bytes());
+        out.extend_from_slice(self.comment);
+        Ok(out)
+    }
+}
+
+fn deflate(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// `extra` without earlier alignment padding, padded again so that an entry whose local header
+/// (without extra field) ends at `header_end` has its data on an `alignment` boundary.
+fn aligned_extra(extra: &[u8], header_end: usize, alignment: usize) -> Vec<u8> {
+    let mut kept = Vec::with_capacity(extra.len());
+    let mut at = 0;
+    while at + 4 <= extra.len() {
+        let id = u16::from_le_bytes([extra[at], extra[at + 1]]);
+        let len = u16::from_le_bytes([extra[at + 2], extra[at + 3]]) as usize;
+        let end = (at + 4 + len).min(extra.len());
+        // Old zipalign padded with zero bytes, which read as empty records with id 0
+        if id != ALIGNMENT_EXTRA_ID && id != 0 {
+            kept.extend_from_slice(&extra[at..end]);
+        }
+        at = end;
+    }
+
+    let data_start = header_end + kept.len();
+    if !data_start.is_multiple_of(alignment) {
+        // Record header, then the alignment as u16, then zeros up to the boundary
+        let zeros = (alignment - (data_start + 6) % alignment) % alignment;
+        kept.extend_from_slice(&ALIGNMENT_EXTRA_ID.to_le_bytes());
+        kept.extend_from_slice(&((2 + zeros) as u16).to_le_bytes());
+        kept.extend_from_slice(&(alignment as u16).to_le_bytes());
+        kept.extend(std::iter::repeat_n(0, zeros));
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func::fixtures::{zip, ZipMember};
+
+    const LIBRARY: &str = "lib/arm64-v8a/libgame.so";
+
+    fn apk() -> Vec<u8> {
+        zip(&[
+            ZipMember::deflated("AndroidManifest.xml", &[0x03; 300]),
+            ZipMember::stored("resources.arsc", b"resources"),
+            ZipMember::stored(LIBRARY, &[0x7F; 1000]),
+            ZipMember::deflated("classes.dex", b"dex\n035 classes"),
+        ])
+    }
+
+    /// `(data offset, entry)` of a member, checking the local header agrees with the central
+    /// directory on CRC32 and sizes.
+    fn layout(archive: &[u8], name: &str) -> (usize, Entry) {
+        let zip = Zip::parse(archive).unwrap();
+        let entry = zip.entry(name).unwrap().clone();
+        let (data_start, _) = zip.local_layout(&entry).unwrap();
+        let local = &archive[entry.local_offset..];
+        assert_eq!(local[14..18], entry.crc.to_le_bytes());
+        assert_eq!(local[18..22], (entry.compressed_size as u32).to_le_bytes());
+        assert_eq!(local[22..26], (entry.size as u32).to_le_bytes());
+        (data_start, entry)
+    }
+
+    #[test]
+    fn member_paths() {
+        let path = member_path("game.xapk!base.apk!lib/x86/libgame.so").unwrap();
+        assert_eq!(path.archive, "game.xapk");
+        assert_eq!(path.members, ["base.apk", "lib/x86/libgame.so"]);
+        assert_eq!(member_path("plain.so"), None);
+        assert_eq!(member_path("game.apk!"), None);
+        assert_eq!(disk_path("game.apk!classes.dex"), "game.apk");
+    }
+
+    #[test]
+    fn stored_member_round_trip() {
+        let archive = apk();
+        let rebuilt = replace_member(&archive, &["resources.arsc"], b"patched resources").unwrap();
+        assert_eq!(
+            read_member(&rebuilt, &["resources.arsc"]).unwrap(),
+            b"patched resources"
+        );
+        let (_, entry) = layout(&rebuilt, "resources.arsc");
+        assert_eq!(entry.crc, crc32fast::hash(b"patched resources"));
+        assert_eq!((entry.compressed_size, entry.size), (17, 17));
+        for name in ["AndroidManifest.xml", "classes.dex", LIBRARY] {
+            assert_eq!(
+                read_member(&rebuilt, &[name]),
+                read_member(&archive, &[name])
+            );
+        }
+    }
+
+    #[test]
+    fn deflated_member_round_trip() {
+        let archive = apk();
+        let patched = [0x42; 5000];
+        let rebuilt = replace_member(&archive, &["classes.dex"], &patched).unwrap();
+        assert_eq!(read_member(&rebuilt, &["classes.dex"]).unwrap(), patched);
+        let (_, entry) = layout(&rebuilt, "classes.dex");
+        assert_eq!(entry.method, DEFLATED);
+        assert_eq!(entry.size, 5000);
+        assert!(entry.compressed_size < 5000);
+        assert_eq!(entry.crc, crc32fast::hash(&patched));
+    }
+
+    #[test]
+    fn stored_data_is_aligned() {
+        let archive = apk();
+        let (start, _) = layout(&archive, LIBRARY);
+        assert_ne!(start % NATIVE_LIBRARY_ALIGNMENT, 0);
+
+        let rebuilt = replace_member(&archive, &[LIBRARY], &[0x90; 1000]).unwrap();
+        let (start, _) = layout(&rebuilt, LIBRARY);
+        assert_eq!(start % NATIVE_LIBRARY_ALIGNMENT, 0);
+        assert_eq!(rebuilt[start..start + 1000], [0x90; 1000]);
+        let (start, _) = layout(&rebuilt, "resources.arsc");
+        assert_eq!(start % STORED_ALIGNMENT, 0);
+
+        // Aligning an already aligned archive changes nothing
+        let again = replace_member(&rebuilt, &[LIBRARY], &[0x90; 1000]).unwrap();
+        assert_eq!(again, rebuilt);
+    }
+
+    #[test]
+    fn data_descriptors() {
+        let archive = zip(&[
+            ZipMember {
+                descriptor: true,
+                ..ZipMember::deflated("streamed.txt", &[0x11; 400])
+            },
+            ZipMember {
+                descriptor: true,
+                ..ZipMember::stored("other.txt", b"other")
+            },
+        ]);
+        assert_eq!(
+            read_member(&archive, &["streamed.txt"]).unwrap(),
+            [0x11; 400]
+        );
+
+        // Untouched entries keep their descriptor, the rewritten one gets its sizes inline
+        let rebuilt = replace_member(&archive, &["streamed.txt"], b"short").unwrap();
+        assert_eq!(read_member(&rebuilt, &["streamed.txt"]).unwrap(), b"short");
+        assert_eq!(read_member(&rebuilt, &["other.txt"]).unwrap(), b"other");
+        let (_, entry) = layout(&rebuilt, "streamed.txt");
+        assert_eq!(entry.flags & FLAG_DATA_DESCRIPTOR, 0);
+        let zip = Zip::parse(&rebuilt).unwrap();
+        let other = zip.entry("other.txt").unwrap();
+        assert_ne!(other.flags & FLAG_DATA_DESCRIPTOR, 0);
+        let (_, end) = zip.local_layout(other).unwrap();
+        assert_eq!(Reader::new(&rebuilt, false).u32(end), Ok(DATA_DESCRIPTOR));
+    }
+
+    #[test]
+    fn nested_members() {
+        let inner = apk();
+        let outer = zip(&[
+            ZipMember::stored("manifest.json", b"{}"),
+            ZipMember::stored("base.apk", &inner),
+        ]);
+        let rebuilt = replace_member(&outer, &["base.apk", LIBRARY], &[0xCC; 10]).unwrap();
+        assert_eq!(
+            read_member(&rebuilt, &["base.apk", LIBRARY]).unwrap(),
+            [0xCC; 10]
+        );
+        assert_eq!(read_member(&rebuilt, &["manifest.json"]).unwrap(), b"{}");
+        assert!(read_member(&rebuilt, &["base.apk", "missing.so"]).is_err());
+    }
+
+    #[test]
+    fn damaged_archives_are_refused() {
+        let mut archive = apk();
+        assert!(read_member(&archive[..archive.len() - 30], &[LIBRARY]).is_err());
+        let (start, _) = layout(&archive, LIBRARY);
+        archive[start] ^= 0xFF;
+        assert!(read_member(&archive, &[LIBRARY])
+            .unwrap_err()
+            .contains("CRC mismatch"));
+    }
+}
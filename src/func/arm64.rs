@@ -0,0 +1,412 @@
+use crate::func::asm::{distance, immediate};
+
+/// Mnemonics listed when an instruction is not supported.
+const SUPPORTED: &str =
+    "mov, movz, movk, movn, ret, nop, b, bl, b.<cond>, cbz, cbnz, cmp, ldr, str";
+
+const SF: u32 = 1 << 31;
+const NOP: u32 = 0xD503_201F;
+const RET: u32 = 0xD65F_0000;
+const B: u32 = 0x1400_0000;
+const BL: u32 = 0x9400_0000;
+const B_COND: u32 = 0x5400_0000;
+const CBZ: u32 = 0x3400_0000;
+const CBNZ: u32 = 0x3500_0000;
+const MOVN: u32 = 0x1280_0000;
+const MOVZ: u32 = 0x5280_0000;
+const MOVK: u32 = 0x7280_0000;
+const ORR_REG: u32 = 0x2A00_03E0;
+const ADD_IMM: u32 = 0x1100_0000;
+const ADDS_IMM: u32 = 0x3100_0000;
+const SUBS_IMM: u32 = 0x7100_0000;
+const SUBS_REG: u32 = 0x6B00_0000;
+const LOAD_STORE_UNSIGNED: u32 = 0x3900_0000;
+const LOAD_STORE_UNSCALED: u32 = 0x3800_0000;
+const LOAD: u32 = 0x0040_0000;
+const POST_INDEX: u32 = 0x400;
+const PRE_INDEX: u32 = 0xC00;
+const LDR_LITERAL: u32 = 0x1800_0000;
+
+const CONDITIONS: [&str; 16] = [
+    "eq", "ne", "cs", "cc", "mi", "pl", "vs", "vc", "hi", "ls", "ge", "lt", "gt", "le", "al", "nv",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Reg {
+    num: u32,
+    wide: bool,
+    /// Register 31 named as `sp`/`wsp` rather than `xzr`/`wzr`.
+    sp: bool,
+}
+
+/// Encodes one lowercase instruction at file offset `pc`.
+pub fn encode(text: &str, pc: u64) -> Result<u32, String> {
+    let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let ops = operands(rest);
+    let ops: Vec<&str> = ops.iter().map(String::as_str).collect();
+
+    match (mnemonic, ops.as_slice()) {
+        ("nop", []) => Ok(NOP),
+        ("ret", []) => Ok(RET | 30 << 5),
+        ("ret", [rn]) => {
+            gp_reg(rn)?;
+            Ok(RET | x_reg(rn)?.num << 5)
+        }
+        ("b", [target]) => Ok(B | branch(target, pc, 26)?),
+        ("bl", [target]) => Ok(BL | branch(target, pc, 26)?),
+        (b_cond, [target]) if b_cond.starts_with("b.") => {
+            let cond = condition(&b_cond[2..])?;
+            Ok(B_COND | branch(target, pc, 19)? << 5 | cond)
+        }
+        ("cbz" | "cbnz", [rt, target]) => {
+            let rt = gp_reg(rt)?;
+            let op = if mnemonic == "cbz" { CBZ } else { CBNZ };
+            Ok(sf(rt) | op | branch(target, pc, 19)? << 5 | rt.num)
+        }
+        ("mov", [rd, source]) if is_immediate(source) => mov_immediate(gp_reg(rd)?, source),
+        ("mov", [rd, rm]) => {
+            let (rd, rm) = (reg(rd)?, reg(rm)?);
+            if rd.wide != rm.wide {
+                return Err("registers must be the same size".into());
+            }
+            if rd.sp || rm.sp {
+                Ok(sf(rd) | ADD_IMM | rm.num << 5 | rd.num)
+            } else {
+                Ok(sf(rd) | ORR_REG | rm.num << 16 | rd.num)
+            }
+        }
+        ("movz" | "movk" | "movn", [rd, imm, shift @ ..]) => {
+            let rd = gp_reg(rd)?;
+            let op = match mnemonic {
+                "movz" => MOVZ,
+                "movk" => MOVK,
+                _ => MOVN,
+            };
+            let value = immediate(imm)?;
+            if !(0..=0xFFFF).contains(&value) {
+                return Err(format!("{} takes a 16-bit immediate", mnemonic));
+            }
+            let shift = match shift {
+                [] => 0,
+                [shift] => lsl(shift)?,
+                _ => return Err("too many operands".into()),
+            };
+            if shift % 16 != 0 || shift >= if rd.wide { 64 } else { 32 } {
+                return Err(format!(
+                    "cannot shift a {}-bit register by {}",
+                    bits(rd),
+                    shift
+                ));
+            }
+            Ok(sf(rd) | op | (shift / 16) << 21 | (value as u32) << 5 | rd.num)
+        }
+        ("cmp", [rn, source, shift @ ..]) if is_immediate(source) => {
+            let rn = reg(rn)?;
+            let mut value = immediate(source)?;
+            let mut shifted = match shift {
+                [] => false,
+                [shift] if lsl(shift)? == 12 => true,
+                [_] => return Err("cmp can only shift its immediate by 12".into()),
+                _ => return Err("too many operands".into()),
+            };
+            // A negative value is compared by adding it instead
+            let op = if value < 0 { ADDS_IMM } else { SUBS_IMM };
+            value = value.abs();
+            if !shifted && value > 0xFFF && value & 0xFFF == 0 {
+                value >>= 12;
+                shifted = true;
+            }
+            if value > 0xFFF {
+                return Err("immediate must fit in 12 bits, optionally shifted by 12".into());
+            }
+            Ok(sf(rn) | op | (shifted as u32) << 22 | (value as u32) << 10 | rn.num << 5 | 31)
+        }
+        ("cmp", [rn, rm]) => {
+            let (rn, rm) = (gp_reg(rn)?, gp_reg(rm)?);
+            if rn.wide != rm.wide {
+                return Err("registers must be the same size".into());
+            }
+            Ok(sf(rn) | SUBS_REG | rm.num << 16 | rn.num << 5 | 31)
+        }
+        ("ldr" | "str", [rt, address, rest @ ..]) => {
+            load_store(mnemonic == "ldr", gp_reg(rt)?, address, rest, pc)
+        }
+        ("nop" | "ret" | "b" | "bl" | "cbz" | "cbnz" | "mov" | "movz" | "movk" | "movn", _)
+        | ("cmp" | "ldr" | "str", _) => Err("wrong number of operands".into()),
+        _ => Err(format!(
+            "unsupported instruction `{}` (supported: {})",
+            mnemonic, SUPPORTED
+        )),
+    }
+}
+
+/// `mov` with an immediate, as a single `movz` or `movn`.
+fn mov_immediate(rd: Reg, source: &str) -> Result<u32, String> {
+    let value = immediate(source)?;
+    let (width, mask) = if rd.wide {
+        (64, u64::MAX)
+    } else {
+        (32, u32::MAX as u64)
+    };
+    if !rd.wide && !(i32::MIN as i64..=u32::MAX as i64).contains(&value) {
+        return Err(format!("{} does not fit in a 32-bit register", value));
+    }
+    let value = value as u64 & mask;
+    for (op, candidate) in [(MOVZ, value), (MOVN, !value & mask)] {
+        for hw in 0..width / 16 {
+            let shift = hw * 16;
+            if candidate & !(0xFFFF << shift) & mask == 0 {
+                let imm16 = (candidate >> shift) as u32 & 0xFFFF;
+                return Ok(sf(rd) | op | (hw as u32) << 21 | imm16 << 5 | rd.num);
+            }
+        }
+    }
+    Err(format!(
+        "0x{:X} cannot be moved in one instruction; use movz followed by movk",
+        value
+    ))
+}
+
+/// `ldr`/`str` with `[xn]`, `[xn, #imm]`, `[xn, #imm]!` or `[xn], #imm`, or `ldr` of a literal.
+fn load_store(load: bool, rt: Reg, address: &str, rest: &[&str], pc: u64) -> Result<u32, String> {
+    let size = if rt.wide { 0xC000_0000 } else { 0x8000_0000 };
+    let opc = if load { LOAD } else { 0 };
+
+    let Some(inner) = address.strip_prefix('[') else {
+        if !load || !rest.is_empty() {
+            return Err("expected a `[base, #offset]` address".into());
+        }
+        let opc = if rt.wide { 1 << 30 } else { 0 };
+        return Ok(LDR_LITERAL | opc | branch(address, pc, 19)? << 5 | rt.num);
+    };
+    let (inner, pre_index) = match inner.strip_suffix("]!") {
+        Some(inner) => (inner, true),
+        None => (
+            inner
+                .strip_suffix(']')
+                .ok_or("unterminated `[` in address")?,
+            false,
+        ),
+    };
+    let mut parts = inner.split(',').map(str::trim);
+    let base = x_reg(parts.next().unwrap_or_default())?;
+    let offset = parts.next().map(immediate).transpose()?.unwrap_or(0);
+    if parts.next().is_some() {
+        return Err("register offsets are not supported".into());
+    }
+
+    let index = match (pre_index, rest) {
+        (true, []) => Some((PRE_INDEX, offset)),
+        (false, [post]) if offset == 0 => Some((POST_INDEX, immediate(post)?)),
+        (false, []) => None,
+        _ => return Err("unsupported addressing mode".into()),
+    };
+    if let Some((mode, offset)) = index {
+        if !(-256..=255).contains(&offset) {
+            return Err("pre/post-index offset must be between -256 and 255".into());
+        }
+        let imm9 = (offset as u32) & 0x1FF;
+        return Ok(size | LOAD_STORE_UNSCALED | opc | imm9 << 12 | mode | base.num << 5 | rt.num);
+    }
+
+    let scale = if rt.wide { 8 } else { 4 };
+    if offset >= 0 && offset % scale == 0 && offset / scale < 4096 {
+        let imm12 = (offset / scale) as u32;
+        Ok(size | LOAD_STORE_UNSIGNED | opc | imm12 << 10 | base.num << 5 | rt.num)
+    } else if (-256..=255).contains(&offset) {
+        let imm9 = (offset as u32) & 0x1FF;
+        Ok(size | LOAD_STORE_UNSCALED | opc | imm9 << 12 | base.num << 5 | rt.num)
+    } else {
+        Err(format!(
+            "offset {} must be a multiple of {} below {}, or between -256 and 255",
+            offset,
+            scale,
+            scale * 4096
+        ))
+    }
+}
+
+/// The `bits`-wide word offset from `pc` to a branch target.
+fn branch(target: &str, pc: u64, bits: u32) -> Result<u32, String> {
+    let delta = distance(target, pc)?;
+    if delta % 4 != 0 {
+        return Err(format!("branch offset {} is not a multiple of 4", delta));
+    }
+    let limit = 1i64 << (bits + 1);
+    if !(-limit..limit).contains(&delta) {
+        return Err(format!(
+            "target is {} bytes away, beyond the {} byte range of this branch",
+            delta, limit
+        ));
+    }
+    Ok(((delta >> 2) as u32) & ((1 << bits) - 1))
+}
+
+fn condition(name: &str) -> Result<u32, String> {
+    let name = match name {
+        "hs" => "cs",
+        "lo" => "cc",
+        name => name,
+    };
+    CONDITIONS
+        .iter()
+        .position(|&cond| cond == name)
+        .map(|cond| cond as u32)
+        .ok_or_else(|| format!("unknown condition `{}`", name))
+}
+
+fn reg(name: &str) -> Result<Reg, String> {
+    let (num, wide, sp) = match name {
+        "sp" => (31, true, true),
+        "wsp" => (31, false, true),
+        "xzr" => (31, true, false),
+        "wzr" => (31, false, false),
+        "lr" => (30, true, false),
+        "fp" => (29, true, false),
+        _ => {
+            let wide = match name.chars().next() {
+                Some('x') => true,
+                Some('w') => false,
+                _ => return Err(format!("unknown register `{}`", name)),
+            };
+            let num: u32 = name[1..]
+                .parse()
+                .ok()
+                .filter(|&num| num <= 30)
+                .ok_or_else(|| format!("unknown register `{}`", name))?;
+            (num, wide, false)
+        }
+    };
+    Ok(Reg { num, wide, sp })
+}
+
+/// A general-purpose register or the zero register, but not `sp`.
+fn gp_reg(name: &str) -> Result<Reg, String> {
+    let reg = reg(name)?;
+    if reg.sp {
+        return Err(format!("`{}` cannot be used here", name));
+    }
+    Ok(reg)
+}
+
+fn x_reg(name: &str) -> Result<Reg, String> {
+    let reg = reg(name)?;
+    if !reg.wide {
+        return Err(format!("expected a 64-bit register, found `{}`", name));
+    }
+    Ok(reg)
+}
+
+fn sf(reg: Reg) -> u32 {
+    if reg.wide {
+        SF
+    } else {
+        0
+    }
+}
+
+fn bits(reg: Reg) -> u32 {
+    if reg.wide {
+        64
+    } else {
+        32
+    }
+}
+
+fn is_immediate(text: &str) -> bool {
+    text.starts_with('#') || text.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+}
+
+/// `lsl #n` as `n`.
+fn lsl(text: &str) -> Result<u32, String> {
+    let amount = text
+        .strip_prefix("lsl")
+        .ok_or_else(|| format!("expected `lsl #n`, found `{}`", text))?;
+    let amount = immediate(amount)?;
+    u32::try_from(amount).map_err(|_| format!("invalid shift `{}`", text))
+}
+
+/// Operands split at commas outside of `[...]`.
+fn operands(text: &str) -> Vec<String> {
+    let mut ops = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    for c in text.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ',' if depth == 0 => {
+                ops.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() {
+        ops.push(current.trim().to_string());
+    }
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodings checked against `llvm-mc -triple=aarch64 -show-encoding`.
+    #[test]
+    fn known_encodings() {
+        for (text, word) in [
+            ("nop", 0xD503_201F),
+            ("ret", 0xD65F_03C0),
+            ("ret x1", 0xD65F_0020),
+            ("mov x0, #1", 0xD280_0020),
+            ("mov w0, #-1", 0x1280_0000),
+            ("mov x1, x2", 0xAA02_03E1),
+            ("mov x29, sp", 0x9100_03FD),
+            ("movk x0, #0x1234, lsl #16", 0xF2A2_4680),
+            ("cmp w0, #5", 0x7100_141F),
+            ("cmp x1, #-3", 0xB100_0C3F),
+            ("cmp x2, x3", 0xEB03_005F),
+            ("ldr x0, [x1, #8]", 0xF940_0420),
+            ("str w2, [sp, #-4]!", 0xB81F_CFE2),
+            ("ldr x3, [x4], #16", 0xF841_0483),
+        ] {
+            assert_eq!(encode(text, 0x1000), Ok(word), "{}", text);
+        }
+    }
+
+    #[test]
+    fn branches_are_relative_to_the_instruction() {
+        for (text, word) in [
+            ("b 0x1010", 0x1400_0004),
+            ("b +16", 0x1400_0004),
+            ("bl 0xff8", 0x97FF_FFFE),
+            ("b.ne +32", 0x5400_0101),
+            ("cbz w3, +8", 0x3400_0043),
+            ("cbnz x4, -4", 0xB5FF_FFE4),
+            ("ldr x5, 0x1008", 0x5800_0045),
+        ] {
+            assert_eq!(encode(text, 0x1000), Ok(word), "{}", text);
+        }
+    }
+
+    #[test]
+    fn invalid_instructions_are_rejected() {
+        for text in [
+            "b +6",
+            "b +0x8000000",
+            "b.xx +4",
+            "mov x0, w1",
+            "movz x0, #0x10000",
+            "movk w0, #1, lsl #32",
+            "cmp x0, #0x1001",
+            "ldr x0, [x1, #4097]",
+            "ret sp",
+            "push x0",
+        ] {
+            assert!(encode(text, 0x1000).is_err(), "{} was accepted", text);
+        }
+    }
+}
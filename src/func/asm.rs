@@ -0,0 +1,66 @@
+use crate::func::binary::Isa;
+use crate::func::{arm64, x86};
+
+/// Encodes one instruction at a file offset, given the bytes the file holds there.
+type Encoder = fn(&str, u64, &[u8]) -> Result<Vec<u8>, String>;
+
+/// Encodes assembly `source` for `isa` as the code starting at file offset `address`, so
+/// branch targets, also given as file offsets, become the right relative displacements.
+/// `original` holds the bytes at `address` before patching, for instructions derived from them.
+///
+/// Instructions are one per line or separated by `;`, and `//` starts a comment.
+pub fn assemble(source: &str, original: &[u8], address: u64, isa: Isa) -> Result<Vec<u8>, String> {
+    let encode: Encoder = match isa {
+        Isa::Arm64 => |text, pc, _| arm64::encode(text, pc).map(|word| word.to_le_bytes().to_vec()),
+        Isa::X86_64 => |text, pc, original| x86::encode(text, pc, original, true),
+        Isa::X86 => |text, pc, original| x86::encode(text, pc, original, false),
+        Isa::Unknown => {
+            return Err("cannot assemble for an unknown architecture; set the file's `arch`".into())
+        }
+        isa => return Err(format!("assembling {} code is not supported", isa)),
+    };
+
+    let mut out = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split("//").next().unwrap_or_default();
+        for text in line
+            .split(';')
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+        {
+            let pc = address + out.len() as u64;
+            let here = original.get(out.len()..).unwrap_or_default();
+            let bytes = encode(&text.to_ascii_lowercase(), pc, here)
+                .map_err(|e| format!("line {}: `{}`: {}", number + 1, text, e))?;
+            out.extend(bytes);
+        }
+    }
+    if out.is_empty() {
+        return Err("no instructions to assemble".into());
+    }
+    Ok(out)
+}
+
+/// A number in decimal or `0x` hex, optionally signed or written as an ARM `#` immediate.
+pub fn immediate(text: &str) -> Result<i64, String> {
+    let text = text.trim().trim_start_matches('#');
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    }
+    .map_err(|_| format!("invalid immediate `{}`", text))?;
+    Ok(if negative { -value } else { value })
+}
+
+/// Bytes from `pc` to a branch target: a file offset, or `+n`/`-n` from `pc` itself.
+pub fn distance(target: &str, pc: u64) -> Result<i64, String> {
+    let target = target.trim().trim_start_matches('#');
+    match target.chars().next() {
+        Some('+' | '-') => immediate(target),
+        _ => Ok(immediate(target)? - pc as i64),
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs::{self, File, FileTimes, Metadata, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Writes `data` to `target` through a temporary file in the same directory that is synced and
+/// renamed over the target, so a failure never leaves a half-written file behind.
+///
+/// When `source` is given its permissions (and on Unix its owner, where allowed) are copied to
+/// the new file; `preserve_times` additionally copies its access and modification times.
+pub fn write_atomic(
+    target: &Path,
+    data: &[u8],
+    source: Option<&Metadata>,
+    preserve_times: bool,
+) -> Result<(), io::Error> {
+    let dir = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let name = target.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid output path '{}'", target.display()),
+        )
+    })?;
+    let temp = dir.join(format!(
+        ".{}.hexsaly-{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let result =
+        write_temp(&temp, data, source, preserve_times).and_then(|()| fs::rename(&temp, target));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result?;
+
+    // Persist the rename itself; not every platform can open a directory for syncing
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn write_temp(
+    temp: &Path,
+    data: &[u8],
+    source: Option<&Metadata>,
+    preserve_times: bool,
+) -> Result<(), io::Error> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(temp)?;
+    file.write_all(data)?;
+
+    if let Some(source) = source {
+        // Ownership first: changing the owner may clear setuid/setgid bits set below
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{fchown, MetadataExt};
+            let _ = fchown(&file, Some(source.uid()), Some(source.gid()));
+        }
+        file.set_permissions(source.permissions())?;
+
+        if preserve_times {
+            let mut times = FileTimes::new().set_modified(source.modified()?);
+            if let Ok(accessed) = source.accessed() {
+                times = times.set_accessed(accessed);
+            }
+            file.set_times(times)?;
+        }
+    }
+
+    file.sync_all()
+}
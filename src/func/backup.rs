@@ -0,0 +1,251 @@
+use crate::func::atomic::write_atomic;
+use crate::func::config::BackupPolicy;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Formats the current UTC time as `YYYYMMDD-HHMMSS`, which sorts chronologically.
+fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+fn file_name(file: &Path) -> Result<String, io::Error> {
+    file.file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid file name '{}'", file.display()),
+            )
+        })
+}
+
+fn parent_dir(file: &Path) -> PathBuf {
+    match file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn timestamped_path(dir: &Path, name: &str) -> PathBuf {
+    let stamp = timestamp();
+    let mut path = dir.join(format!("{}.{}.bak", name, stamp));
+    let mut counter = 1;
+    while path.exists() {
+        path = dir.join(format!("{}.{}-{}.bak", name, stamp, counter));
+        counter += 1;
+    }
+    path
+}
+
+/// Whether `candidate` is a backup of the file called `name`.
+fn is_backup_of(candidate: &str, name: &str) -> bool {
+    candidate
+        .strip_prefix(name)
+        .and_then(|rest| rest.strip_suffix(".bak"))
+        .is_some_and(|stamp| {
+            stamp.is_empty()
+                || (stamp.starts_with('.')
+                    && stamp[1..].bytes().all(|b| b.is_ascii_digit() || b == b'-'))
+        })
+}
+
+/// Copies `file` aside according to `policy`, returning the backup path if one was made.
+pub fn create_backup(file: &Path, policy: &BackupPolicy) -> Result<Option<PathBuf>, io::Error> {
+    if !file.exists() {
+        return Ok(None);
+    }
+    let name = file_name(file)?;
+
+    let path = match policy {
+        BackupPolicy::None => return Ok(None),
+        BackupPolicy::Bak => file.with_file_name(format!("{}.bak", name)),
+        BackupPolicy::Timestamped => {
+            timestamped_path(file.parent().unwrap_or(Path::new("")), &name)
+        }
+        BackupPolicy::Directory { path, .. } => {
+            fs::create_dir_all(path)?;
+            timestamped_path(Path::new(path), &name)
+        }
+    };
+    fs::copy(file, &path)?;
+
+    if let BackupPolicy::Directory {
+        path: dir,
+        keep: Some(keep),
+    } = policy
+    {
+        prune_backups(file, Path::new(dir), *keep)?;
+    }
+    Ok(Some(path))
+}
+
+/// Deletes the oldest backups of `file` in `dir` so that at most `keep` remain.
+fn prune_backups(file: &Path, dir: &Path, keep: usize) -> Result<(), io::Error> {
+    let backups = find_backups(&file_name(file)?, &[dir.to_path_buf()])?;
+    for stale in backups.iter().skip(keep) {
+        fs::remove_file(stale)?;
+    }
+    Ok(())
+}
+
+fn find_backups(name: &str, dirs: &[PathBuf]) -> Result<Vec<PathBuf>, io::Error> {
+    let mut backups: Vec<(SystemTime, PathBuf)> = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            let is_backup = entry
+                .file_name()
+                .to_str()
+                .is_some_and(|candidate| is_backup_of(candidate, name));
+            if is_backup && !backups.iter().any(|(_, known)| *known == path) {
+                backups.push((entry.metadata()?.modified()?, path));
+            }
+        }
+    }
+
+    backups.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.cmp(&a.1)));
+    Ok(backups.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Lists backups of `file` next to it and in `dirs`, newest first.
+pub fn list_backups(file: &Path, dirs: &[PathBuf]) -> Result<Vec<PathBuf>, io::Error> {
+    let mut search = vec![parent_dir(file)];
+    search.extend(dirs.iter().cloned());
+    find_backups(&file_name(file)?, &search)
+}
+
+/// Copies `backup` back over `file`.
+pub fn restore_backup(backup: &Path, file: &Path) -> Result<(), io::Error> {
+    let data = fs::read(backup)?;
+    write_atomic(file, &data, Some(&fs::metadata(backup)?), false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func::fixtures::scratch_dir;
+    use std::fs::File;
+    use std::time::Duration;
+
+    /// Writes `name` in `dir`, last modified `age_secs` ago.
+    fn aged(dir: &Path, name: &str, age_secs: u64) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, name).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(age_secs))
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn backups_are_recognised_by_name() {
+        for candidate in [
+            "game.bin.bak",
+            "game.bin.20261017-093000.bak",
+            "game.bin.20261017-093000-2.bak",
+        ] {
+            assert!(is_backup_of(candidate, "game.bin"), "{}", candidate);
+        }
+        for candidate in [
+            "game.bin",
+            "game.binx.bak",
+            "game.bin.old.bak",
+            "game.bin.20261017-093000.bak.tmp",
+            "other.game.bin.bak",
+        ] {
+            assert!(!is_backup_of(candidate, "game.bin"), "{}", candidate);
+        }
+        let stamp = timestamp();
+        assert_eq!(stamp.len(), 15);
+        assert!(is_backup_of(&format!("game.bin.{}.bak", stamp), "game.bin"));
+    }
+
+    #[test]
+    fn directory_backups_keep_the_newest() {
+        let dir = scratch_dir("backup-keep");
+        let backups = dir.join("backups");
+        fs::create_dir(&backups).unwrap();
+        let file = dir.join("game.bin");
+        fs::write(&file, "current").unwrap();
+        let oldest = aged(&backups, "game.bin.20260101-000000.bak", 300);
+        let older = aged(&backups, "game.bin.20260201-000000.bak", 200);
+        let kept = aged(&backups, "game.bin.20260301-000000.bak", 100);
+        let unrelated = aged(&backups, "other.bin.20250101-000000.bak", 900);
+
+        let policy = BackupPolicy::Directory {
+            path: backups.to_string_lossy().into_owned(),
+            keep: Some(2),
+        };
+        let created = create_backup(&file, &policy).unwrap().unwrap();
+        assert_eq!(fs::read(&created).unwrap(), b"current");
+        assert!(!oldest.exists() && !older.exists());
+        assert!(kept.exists() && unrelated.exists());
+        assert_eq!(list_backups(&file, &[backups]).unwrap(), [created, kept]);
+    }
+
+    #[test]
+    fn backups_are_listed_newest_first() {
+        let dir = scratch_dir("backup-list");
+        let file = dir.join("game.bin");
+        fs::write(&file, "patched").unwrap();
+        let bak = aged(&dir, "game.bin.bak", 50);
+        let stamped = aged(&dir, "game.bin.20260101-000000.bak", 500);
+        // The file's own directory given again is not searched twice
+        assert_eq!(list_backups(&file, &[dir]).unwrap(), [bak.clone(), stamped]);
+
+        restore_backup(&bak, &file).unwrap();
+        assert_eq!(fs::read(&file).unwrap(), b"game.bin.bak");
+    }
+
+    #[test]
+    fn bak_backups_are_replaced() {
+        let dir = scratch_dir("backup-bak");
+        let file = dir.join("game.bin");
+        assert_eq!(create_backup(&file, &BackupPolicy::Bak).unwrap(), None);
+        fs::write(&file, "first").unwrap();
+        let bak = create_backup(&file, &BackupPolicy::Bak).unwrap().unwrap();
+        fs::write(&file, "second").unwrap();
+        assert_eq!(
+            create_backup(&file, &BackupPolicy::Bak).unwrap(),
+            Some(bak.clone())
+        );
+        assert_eq!(fs::read(&bak).unwrap(), b"second");
+        assert_eq!(create_backup(&file, &BackupPolicy::None).unwrap(), None);
+        assert_eq!(list_backups(&file, &[]).unwrap(), [bak]);
+    }
+}
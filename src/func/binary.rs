@@ -0,0 +1,478 @@
+use crate::func::config::FileFormat;
+use crate::func::elf::Elf;
+use crate::func::macho::{self, MachO};
+use crate::func::pe::Pe;
+use std::fmt;
+use std::ops::Range;
+
+/// A mapped region of the file: `file_size` bytes at `offset` load at `vaddr`.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub name: String,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub file_size: u64,
+    pub mem_size: u64,
+}
+
+impl Segment {
+    /// The file bytes of the segment, or `None` when its header runs past the address space.
+    fn file_range(&self) -> Option<Range<u64>> {
+        let end = self.offset.checked_add(self.file_size)?;
+        Some(self.offset..end)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub name: String,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub size: u64,
+}
+
+const EM_386: u16 = 3;
+const EM_ARM: u16 = 40;
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+const IMAGE_FILE_MACHINE_I386: u16 = 0x14C;
+const IMAGE_FILE_MACHINE_ARM: u16 = 0x1C0;
+const IMAGE_FILE_MACHINE_ARMNT: u16 = 0x1C4;
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+const IMAGE_FILE_MACHINE_ARM64: u16 = 0xAA64;
+
+/// Instruction set of a binary's code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Isa {
+    X86,
+    X86_64,
+    Arm,
+    /// 32-bit ARM code in the Thumb-2 encoding.
+    Thumb,
+    Arm64,
+    Unknown,
+}
+
+impl Isa {
+    /// The instruction set for an architecture name such as `arm64`, `aarch64` or `x86_64`,
+    /// accepting the `lipo` names used for universal binary slices.
+    pub fn from_name(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "x86_64" | "x86_64h" | "x86-64" | "amd64" | "x64" => Isa::X86_64,
+            "i386" | "i686" | "x86" => Isa::X86,
+            "arm64" | "arm64e" | "aarch64" => Isa::Arm64,
+            "thumb" | "thumbv7" | "thumb2" | "t32" => Isa::Thumb,
+            name if name.starts_with("arm") => Isa::Arm,
+            _ => Isa::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for Isa {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Isa::X86 => "x86",
+            Isa::X86_64 => "x86_64",
+            Isa::Arm => "arm",
+            Isa::Thumb => "thumb",
+            Isa::Arm64 => "arm64",
+            Isa::Unknown => "unknown",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Structure of the file being patched, used to translate addresses and guard writes.
+#[derive(Debug, Clone)]
+pub enum Binary {
+    Raw,
+    Elf(Elf),
+    Pe(Pe),
+    MachO(MachO),
+}
+
+impl Binary {
+    pub fn load(data: &[u8], format: FileFormat) -> Result<Self, String> {
+        match format {
+            FileFormat::Raw => Ok(Binary::Raw),
+            FileFormat::Elf => Elf::parse(data).map(Binary::Elf),
+            FileFormat::Pe => Pe::parse(data).map(Binary::Pe),
+            FileFormat::Auto if Elf::is_elf(data) => Elf::parse(data).map(Binary::Elf),
+            FileFormat::MachO => MachO::parse(data).map(Binary::MachO),
+            FileFormat::Auto if Pe::is_pe(data) => Pe::parse(data).map(Binary::Pe),
+            FileFormat::Auto if MachO::is_macho(data) => MachO::parse(data).map(Binary::MachO),
+            FileFormat::Auto if macho::fat_slices(data).is_some() => {
+                Err("universal binaries must be patched one slice at a time".into())
+            }
+            FileFormat::Auto => Ok(Binary::Raw),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Binary::Raw => "raw",
+            Binary::Elf(_) => "ELF",
+            Binary::Pe(_) => "PE",
+            Binary::MachO(_) => "Mach-O",
+        }
+    }
+
+    /// Instruction set the binary's headers declare.
+    pub fn isa(&self) -> Isa {
+        match self {
+            Binary::Raw => Isa::Unknown,
+            Binary::Elf(elf) => match elf.machine {
+                EM_386 => Isa::X86,
+                EM_X86_64 => Isa::X86_64,
+                EM_ARM => Isa::Arm,
+                EM_AARCH64 => Isa::Arm64,
+                _ => Isa::Unknown,
+            },
+            Binary::Pe(pe) => match pe.machine {
+                IMAGE_FILE_MACHINE_I386 => Isa::X86,
+                IMAGE_FILE_MACHINE_AMD64 => Isa::X86_64,
+                IMAGE_FILE_MACHINE_ARM => Isa::Arm,
+                IMAGE_FILE_MACHINE_ARMNT => Isa::Thumb,
+                IMAGE_FILE_MACHINE_ARM64 => Isa::Arm64,
+                _ => Isa::Unknown,
+            },
+            Binary::MachO(macho) => Isa::from_name(&macho.arch),
+        }
+    }
+
+    /// The instruction set of the code patched under a file entry's `arch`, which names it for
+    /// files without headers and picks Thumb over ARM for 32-bit ARM ones.
+    pub fn code_isa(&self, arch: Option<&str>) -> Isa {
+        match (self.isa(), arch.map(Isa::from_name)) {
+            (Isa::Unknown, Some(isa)) | (Isa::Arm, Some(isa @ Isa::Thumb)) => isa,
+            (isa, _) => isa,
+        }
+    }
+
+    fn segments(&self) -> &[Segment] {
+        match self {
+            Binary::Raw => &[],
+            Binary::Elf(elf) => &elf.segments,
+            Binary::Pe(pe) => &pe.segments,
+            Binary::MachO(macho) => &macho.segments,
+        }
+    }
+
+    fn sections(&self) -> &[Section] {
+        match self {
+            Binary::Raw => &[],
+            Binary::Elf(elf) => &elf.sections,
+            Binary::Pe(pe) => &pe.sections,
+            Binary::MachO(macho) => &macho.sections,
+        }
+    }
+
+    fn image_base(&self) -> u64 {
+        match self {
+            Binary::Raw => 0,
+            Binary::Elf(elf) => elf.image_base(),
+            Binary::Pe(pe) => pe.image_base,
+            Binary::MachO(macho) => macho.image_base(),
+        }
+    }
+
+    fn require_structure(&self, what: &str) -> Result<(), String> {
+        match self {
+            Binary::Raw => Err(format!(
+                "{} needs a recognised executable format, but the file is raw data",
+                what
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// File offset of the byte loaded at virtual address `va`.
+    pub fn va_to_offset(&self, va: u64) -> Result<usize, String> {
+        self.require_structure("va")?;
+        for segment in self.segments() {
+            let delta = match va.checked_sub(segment.vaddr) {
+                Some(delta) if delta < segment.mem_size.max(segment.file_size) => delta,
+                _ => continue,
+            };
+            if delta >= segment.file_size {
+                return Err(format!(
+                    "address 0x{:X} is in zero-filled memory not backed by the file",
+                    va
+                ));
+            }
+            match segment.offset.checked_add(delta) {
+                Some(offset) => return to_usize(offset),
+                None => continue,
+            }
+        }
+        Err(format!(
+            "address 0x{:X} is not inside any loadable segment",
+            va
+        ))
+    }
+
+    /// File offset of the byte at `rva`, relative to the image's load address.
+    pub fn rva_to_offset(&self, rva: u64) -> Result<usize, String> {
+        self.require_structure("rva")?;
+        let va = self
+            .image_base()
+            .checked_add(rva)
+            .ok_or_else(|| format!("rva 0x{:X} is out of range", rva))?;
+        self.va_to_offset(va)
+    }
+
+    /// Virtual address the byte at file `offset` is loaded at.
+    pub fn offset_to_va(&self, offset: usize) -> Result<u64, String> {
+        self.require_structure("va")?;
+        let at = offset as u64;
+        self.segments()
+            .iter()
+            .filter(|segment| {
+                segment
+                    .file_range()
+                    .is_some_and(|range| range.contains(&at))
+            })
+            .find_map(|segment| segment.vaddr.checked_add(at - segment.offset))
+            .ok_or_else(|| format!("offset 0x{:X} is not inside any loadable segment", offset))
+    }
+
+    /// The `rva` of the byte at file `offset`, relative to the image's load address.
+    pub fn offset_to_rva(&self, offset: usize) -> Result<u64, String> {
+        self.offset_to_va(offset)?
+            .checked_sub(self.image_base())
+            .ok_or_else(|| format!("offset 0x{:X} lies below the image base", offset))
+    }
+
+    /// True for a Mach-O image carrying a code signature that patching will invalidate.
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Binary::MachO(macho) if macho.code_signature.is_some())
+    }
+
+    /// File range of the named section. Mach-O sections may be named with or without their
+    /// segment (`__text` or `__TEXT,__text`).
+    pub fn section_range(&self, name: &str) -> Result<Range<usize>, String> {
+        self.require_structure("section")?;
+        let section = self
+            .sections()
+            .iter()
+            .find(|section| {
+                section.name == name
+                    || section
+                        .name
+                        .split_once(',')
+                        .is_some_and(|(_, short)| short == name)
+            })
+            .ok_or_else(|| {
+                let known: Vec<&str> = self.sections().iter().map(|s| s.name.as_str()).collect();
+                format!(
+                    "no section named '{}' (available: {})",
+                    name,
+                    known.join(", ")
+                )
+            })?;
+        let start = to_usize(section.offset)?;
+        Ok(start..start.saturating_add(to_usize(section.size)?))
+    }
+
+    /// Refuses writes that would not end up in memory when the binary is loaded.
+    pub fn check_write(&self, offset: usize, len: usize) -> Result<(), String> {
+        if matches!(self, Binary::Raw) {
+            return Ok(());
+        }
+        let start = offset as u64;
+        let end = start.checked_add(len.max(1) as u64);
+        let inside = self.segments().iter().any(|segment| {
+            segment
+                .file_range()
+                .zip(end)
+                .is_some_and(|(range, end)| start >= range.start && end <= range.end)
+        });
+        if inside {
+            Ok(())
+        } else {
+            Err(format!(
+                "write of {} byte(s) at offset 0x{:X} is outside every loadable {} segment",
+                len,
+                offset,
+                self.kind()
+            ))
+        }
+    }
+}
+
+/// The byte range of `data` that patches apply to: the slice for `arch` in a universal binary,
+/// or the whole file otherwise.
+pub fn select_slice(
+    data: &[u8],
+    arch: Option<&str>,
+) -> Result<(Range<usize>, Option<String>), String> {
+    let Some(slices) = macho::fat_slices(data) else {
+        return Ok((0..data.len(), None));
+    };
+    let slices = slices?;
+    let names = || {
+        slices
+            .iter()
+            .map(|slice| slice.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let slice = match arch {
+        Some(arch) => slices
+            .iter()
+            .find(|slice| slice.name == arch)
+            .ok_or_else(|| {
+                format!(
+                    "no {} slice in universal binary (available: {})",
+                    arch,
+                    names()
+                )
+            })?,
+        None if slices.len() == 1 => &slices[0],
+        None => {
+            return Err(format!(
+                "universal binary has several slices; choose one with `arch` (available: {})",
+                names()
+            ))
+        }
+    };
+    Ok((slice.range(), Some(slice.name.clone())))
+}
+
+fn to_usize(value: u64) -> Result<usize, String> {
+    usize::try_from(value).map_err(|_| format!("offset 0x{:X} is out of range", value))
+}
+
+/// Bounds-checked integer reads in a fixed byte order.
+pub struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8], big_endian: bool) -> Self {
+        Reader { data, big_endian }
+    }
+
+    fn bytes<const N: usize>(&self, at: usize) -> Result<[u8; N], String> {
+        at.checked_add(N)
+            .and_then(|end| self.data.get(at..end))
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| format!("truncated header at offset 0x{:X}", at))
+    }
+
+    pub fn u16(&self, at: usize) -> Result<u16, String> {
+        let bytes = self.bytes(at)?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    pub fn u32(&self, at: usize) -> Result<u32, String> {
+        let bytes = self.bytes(at)?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    pub fn u64(&self, at: usize) -> Result<u64, String> {
+        let bytes = self.bytes(at)?;
+        Ok(if self.big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        })
+    }
+
+    /// NUL-terminated string starting at `at`, not reading past `end`.
+    pub fn c_str(&self, at: usize, end: usize) -> Result<String, String> {
+        let bytes = self
+            .data
+            .get(at..end.min(self.data.len()))
+            .ok_or_else(|| format!("string at offset 0x{:X} is out of range", at))?;
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func::fixtures::{elf64, fat, macho64};
+
+    fn elf(segments: &[(u64, u64, u64, u64)]) -> Binary {
+        let data = elf64(0x3000, segments, &[(".text", 0x200, 0x40_0200, 0x100)]);
+        Binary::load(&data, FileFormat::Auto).unwrap()
+    }
+
+    #[test]
+    fn virtual_addresses_map_through_segments() {
+        let binary = elf(&[
+            (0, 0x40_0000, 0x1000, 0x1000),
+            (0x1000, 0x40_2000, 0x800, 0x2000),
+        ]);
+        assert_eq!(binary.va_to_offset(0x40_0123), Ok(0x123));
+        assert_eq!(binary.va_to_offset(0x40_2010), Ok(0x1010));
+        assert_eq!(binary.rva_to_offset(0x2010), Ok(0x1010));
+        assert!(binary
+            .va_to_offset(0x40_2900)
+            .unwrap_err()
+            .contains("zero-filled"));
+        assert!(binary.va_to_offset(0x40_1000).is_err());
+        assert!(binary.rva_to_offset(u64::MAX).is_err());
+
+        assert_eq!(binary.offset_to_va(0x1010), Ok(0x40_2010));
+        assert_eq!(binary.offset_to_rva(0x123), Ok(0x123));
+        assert!(binary.offset_to_va(0x1800).is_err());
+        assert_eq!(binary.section_range(".text"), Ok(0x200..0x300));
+        assert!(binary.section_range(".bss").is_err());
+    }
+
+    #[test]
+    fn writes_must_stay_inside_a_segment() {
+        let binary = elf(&[(0, 0x40_0000, 0x1000, 0x1000)]);
+        assert!(binary.check_write(0xFF0, 0x10).is_ok());
+        assert!(binary.check_write(0xFF0, 0x11).is_err());
+        assert!(binary.check_write(0x2000, 1).is_err());
+        assert!(binary.check_write(usize::MAX, 2).is_err());
+        assert!(Binary::Raw.check_write(0x2000, 1).is_ok());
+    }
+
+    #[test]
+    fn overflowing_segment_headers_map_nothing() {
+        let binary = elf(&[
+            (u64::MAX - 0x10, 0x80_0000, 0x100, 0x100),
+            (0x1000, u64::MAX - 0x10, 0x800, 0x800),
+        ]);
+        assert!(binary.va_to_offset(0x80_0020).is_err());
+        assert!(binary.check_write(0x10, 1).is_err());
+        assert!(binary.offset_to_va(0x1100).is_err());
+    }
+
+    #[test]
+    fn slices_are_selected_by_arch() {
+        let x86 = macho64(0x1000, 0x0100_0007, &[("__TEXT", 0, 0x1000, 0, 0x1000)]);
+        let arm = macho64(0x1000, 0x0100_000C, &[("__TEXT", 0, 0x1000, 0, 0x1000)]);
+        let data = fat(&[(0x0100_0007, &x86), (0x0100_000C, &arm)]);
+        assert_eq!(
+            select_slice(&data, Some("arm64")).unwrap(),
+            (0x2000..0x3000, Some("arm64".into()))
+        );
+        let error = select_slice(&data, None).unwrap_err();
+        assert!(error.contains("several slices") && error.contains("x86_64, arm64"));
+        let error = select_slice(&data, Some("armv7")).unwrap_err();
+        assert!(error.contains("no armv7 slice"));
+
+        // A universal binary of one slice needs no `arch`, and a thin one is used whole
+        let single = fat(&[(0x0100_000C, &arm)]);
+        assert_eq!(select_slice(&single, None).unwrap().0, 0x1000..0x2000);
+        assert_eq!(
+            select_slice(&arm, Some("arm64")).unwrap(),
+            (0..0x1000, None)
+        );
+    }
+}
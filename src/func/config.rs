@@ -0,0 +1,544 @@
+use crate::func::asm::assemble;
+use crate::func::binary::Isa;
+use crate::func::hex::{parse_address, parse_bytes, parse_offset};
+use crate::func::pattern::Pattern;
+use crate::func::value::encode_value;
+use serde::{Deserialize, Deserializer};
+use serde_json::Map;
+use std::fmt;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(rename = "Hexsaly")]
+    pub hexsaly: Hexsaly,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hexsaly {
+    #[serde(default = "default_true")]
+    pub style: bool,
+    #[serde(default = "default_true")]
+    pub menu: bool,
+    #[serde(default)]
+    pub on_mismatch: MismatchPolicy,
+    #[serde(default = "default_true")]
+    pub journal: bool,
+    #[serde(default)]
+    pub backup: BackupPolicy,
+    #[serde(default)]
+    pub preserve_times: bool,
+    /// Keep a parsed index of each file's symbol sources on disk for later runs.
+    #[serde(default)]
+    pub symbol_cache: bool,
+    /// Print the instructions at each patch site before and after it is written.
+    #[serde(default)]
+    pub disasm: bool,
+    pub files: Vec<FileEntry>,
+}
+
+/// What to do when a patch's `expect` bytes do not match the file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MismatchPolicy {
+    /// Log a warning and continue with the next patch.
+    #[default]
+    Skip,
+    /// Abort the current file without writing it.
+    FailFile,
+    /// Abort the whole run.
+    FailRun,
+}
+
+/// How a file is copied aside before it is patched in place.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupPolicy {
+    None,
+    /// `<file>.bak`, replaced on every run.
+    #[default]
+    Bak,
+    /// `<file>.<YYYYMMDD-HHMMSS>.bak` next to the file.
+    Timestamped,
+    /// Timestamped copies in `path`, keeping only the newest `keep`.
+    #[serde(rename = "dir")]
+    Directory {
+        path: String,
+        #[serde(default)]
+        keep: Option<usize>,
+    },
+}
+
+/// Executable format used to resolve `va`/`rva`/`section` and to guard writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileFormat {
+    /// Detect the format from the file's magic bytes.
+    #[default]
+    Auto,
+    /// Treat the file as plain bytes with no structure checks.
+    Raw,
+    Elf,
+    Pe,
+    #[serde(rename = "macho")]
+    MachO,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileEntry {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(alias = "open")]
+    pub input: String,
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(default)]
+    pub dump_cs: Option<String>,
+    /// Further Il2CppDumper outputs to resolve names from: `script.json` or `stringliteral.json`.
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    /// Default address column for this file's `method_name` patches.
+    #[serde(default)]
+    pub method_address: AddressKind,
+    #[serde(default)]
+    pub require: bool,
+    #[serde(default)]
+    pub on_mismatch: Option<MismatchPolicy>,
+    #[serde(default)]
+    pub backup: Option<BackupPolicy>,
+    #[serde(default)]
+    pub preserve_times: Option<bool>,
+    #[serde(default)]
+    pub format: FileFormat,
+    /// Architecture slice to patch in a universal (fat) Mach-O binary, e.g. `arm64`.
+    #[serde(default)]
+    pub arch: Option<String>,
+    /// Recompute the PE optional header `CheckSum` after patching.
+    #[serde(default)]
+    pub update_checksum: bool,
+    pub patches: Vec<Patch>,
+}
+
+impl FileEntry {
+    pub fn title(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.input)
+    }
+
+    pub fn output(&self) -> &str {
+        self.output.as_deref().unwrap_or(&self.input)
+    }
+
+    /// Every symbol source for this file: `dump_cs` first, then `symbols`.
+    pub fn symbol_sources(&self) -> Vec<String> {
+        self.dump_cs.iter().chain(&self.symbols).cloned().collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawPatch")]
+pub struct Patch {
+    pub locator: Locator,
+    pub action: Action,
+    pub position: Option<usize>,
+    pub expect: Option<Pattern>,
+    pub occurrence: Occurrence,
+    pub section: Option<String>,
+    /// Which address column of the symbols a `method_name` patch uses, overriding the file's.
+    pub address: Option<AddressKind>,
+}
+
+/// Address of a method in the symbol sources to patch at: its `Offset`, or its `RVA`/`VA`
+/// translated to a file offset through the binary's headers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressKind {
+    #[default]
+    Offset,
+    Rva,
+    Va,
+}
+
+/// Which matches of a wildcard pattern a patch is applied to. `nth` counts from 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Occurrence {
+    #[default]
+    First,
+    All,
+    Last,
+    Nth(usize),
+    /// Every match, failing unless there are exactly this many.
+    Exactly(usize),
+}
+
+#[derive(Debug, Clone)]
+pub enum Locator {
+    Offset(usize),
+    /// Virtual address, translated to a file offset through the binary's segments.
+    Va(u64),
+    /// Address relative to the binary's load address.
+    Rva(u64),
+    Wildcard(Pattern),
+    MethodName(String),
+    /// A string literal from the symbol sources, patched at its metadata reference.
+    StringLiteral(String),
+}
+
+impl fmt::Display for Locator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Locator::Offset(offset) => write!(f, "0x{:X}", offset),
+            Locator::Va(va) => write!(f, "va 0x{:X}", va),
+            Locator::Rva(rva) => write!(f, "rva 0x{:X}", rva),
+            Locator::Wildcard(pattern) => write!(f, "{}", pattern),
+            Locator::MethodName(name) => write!(f, "{}", name),
+            Locator::StringLiteral(value) => write!(f, "string {:?}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Action {
+    Replace(Payload),
+    Insert(Payload),
+}
+
+impl Action {
+    pub fn payload(&self) -> &Payload {
+        match self {
+            Action::Replace(payload) | Action::Insert(payload) => payload,
+        }
+    }
+}
+
+/// What a patch writes: bytes as given, or assembly encoded for the place it is written to.
+#[derive(Debug, Clone)]
+pub enum Payload {
+    Hex(Vec<u8>),
+    Asm(String),
+}
+
+impl Payload {
+    /// The bytes to write at file offset `offset` of code for `isa`, over the `original` bytes
+    /// found there.
+    pub fn encode(&self, original: &[u8], offset: usize, isa: Isa) -> Result<Vec<u8>, String> {
+        match self {
+            Payload::Hex(bytes) => Ok(bytes.clone()),
+            Payload::Asm(source) => assemble(source, original, offset as u64, isa),
+        }
+    }
+}
+
+/// On-disk shape of a patch entry, checked for exactly one locator and one action.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPatch {
+    offset: Option<HexOffset>,
+    va: Option<HexAddress>,
+    rva: Option<HexAddress>,
+    wildcard: Option<HexPattern>,
+    method_name: Option<String>,
+    string_literal: Option<String>,
+    hex_replace: Option<HexBytes>,
+    hex_insert: Option<HexBytes>,
+    asm_replace: Option<String>,
+    asm_insert: Option<String>,
+    write: Option<WriteBytes>,
+    position: Option<usize>,
+    expect: Option<HexPattern>,
+    occurrence: Option<Occurrence>,
+    section: Option<String>,
+    address: Option<AddressKind>,
+}
+
+impl TryFrom<RawPatch> for Patch {
+    type Error = String;
+
+    fn try_from(raw: RawPatch) -> Result<Self, Self::Error> {
+        let mut locators: Vec<Locator> = [
+            raw.offset.map(|HexOffset(offset)| Locator::Offset(offset)),
+            raw.va.map(|HexAddress(va)| Locator::Va(va)),
+            raw.rva.map(|HexAddress(rva)| Locator::Rva(rva)),
+            raw.wildcard
+                .map(|HexPattern(pattern)| Locator::Wildcard(pattern)),
+            raw.method_name.map(Locator::MethodName),
+            raw.string_literal.map(Locator::StringLiteral),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if locators.len() != 1 {
+            return Err(
+                "patch must have exactly one of `offset`, `va`, `rva`, `wildcard`, `method_name` or `string_literal`"
+                    .into(),
+            );
+        }
+        let locator = locators.remove(0);
+
+        let mut actions: Vec<Action> = [
+            raw.hex_replace
+                .map(|HexBytes(bytes)| Action::Replace(Payload::Hex(bytes))),
+            raw.hex_insert
+                .map(|HexBytes(bytes)| Action::Insert(Payload::Hex(bytes))),
+            raw.asm_replace
+                .map(|source| Action::Replace(Payload::Asm(source))),
+            raw.asm_insert
+                .map(|source| Action::Insert(Payload::Asm(source))),
+            raw.write
+                .map(|WriteBytes(bytes)| Action::Replace(Payload::Hex(bytes))),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if actions.len() != 1 {
+            return Err(
+                "patch must have exactly one of `hex_replace`, `hex_insert`, `asm_replace`, `asm_insert` or `write`"
+                    .into(),
+            );
+        }
+        let action = actions.remove(0);
+
+        let is_wildcard = matches!(locator, Locator::Wildcard(_));
+        if raw.position.is_some() && !is_wildcard {
+            return Err("`position` is only valid together with `wildcard`".into());
+        }
+        if raw.occurrence.is_some() && !is_wildcard {
+            return Err("`occurrence` is only valid together with `wildcard`".into());
+        }
+        if raw.section.is_some() && !is_wildcard {
+            return Err("`section` is only valid together with `wildcard`".into());
+        }
+        if raw.address.is_some() && !matches!(locator, Locator::MethodName(_)) {
+            return Err("`address` is only valid together with `method_name`".into());
+        }
+        if raw.occurrence == Some(Occurrence::Nth(0)) {
+            return Err("`occurrence.nth` counts from 1".into());
+        }
+
+        Ok(Patch {
+            locator,
+            action,
+            position: raw.position,
+            expect: raw.expect.map(|HexPattern(pattern)| pattern),
+            occurrence: raw.occurrence.unwrap_or_default(),
+            section: raw.section,
+            address: raw.address,
+        })
+    }
+}
+
+struct HexOffset(usize);
+
+impl<'de> Deserialize<'de> for HexOffset {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        parse_offset(&text)
+            .map(HexOffset)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+struct HexAddress(u64);
+
+impl<'de> Deserialize<'de> for HexAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        parse_address(&text)
+            .map(HexAddress)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+struct HexBytes(Vec<u8>);
+
+impl<'de> Deserialize<'de> for HexBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let bytes = parse_bytes(&text).map_err(serde::de::Error::custom)?;
+        if bytes.is_empty() {
+            return Err(serde::de::Error::custom("hex string is empty"));
+        }
+        Ok(HexBytes(bytes))
+    }
+}
+
+/// A typed `write` value, encoded once the config is read.
+struct WriteBytes(Vec<u8>);
+
+impl<'de> Deserialize<'de> for WriteBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fields = Map::deserialize(deserializer)?;
+        encode_value(&fields)
+            .map(WriteBytes)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+struct HexPattern(Pattern);
+
+impl<'de> Deserialize<'de> for HexPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Pattern::parse(&text)
+            .map(HexPattern)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Reads `value` as `read_config` does, returning the error with its path.
+    fn read(value: serde_json::Value) -> Result<Config, String> {
+        let text = value.to_string();
+        let mut deserializer = serde_json::Deserializer::from_str(&text);
+        serde_path_to_error::deserialize(&mut deserializer)
+            .map_err(|e| format!("{}: {}", e.path(), e.inner()))
+    }
+
+    fn with_patches(patches: serde_json::Value) -> serde_json::Value {
+        json!({"Hexsaly": {"files": [
+            {"input": "a.bin", "patches": [{"offset": "0x0", "hex_replace": "90"}]},
+            {"input": "b.bin", "patches": patches}
+        ]}})
+    }
+
+    fn patch_error(patch: serde_json::Value) -> String {
+        read(with_patches(
+            json!([{"offset": "0x0", "hex_replace": "90"}, patch]),
+        ))
+        .unwrap_err()
+    }
+
+    #[test]
+    fn files_and_patches_are_typed() {
+        let config = read(with_patches(json!([
+            {"offset": "0x10", "expect": "AA ??", "hex_replace": "90 90"},
+            {"wildcard": "DE AD ?? EF", "position": 2, "occurrence": {"nth": 2}, "hex_insert": "CC"},
+            {"method_name": "Player::Update", "address": "rva", "asm_replace": "ret"},
+            {"rva": "0x1000", "write": {"u16le": 7}}
+        ])))
+        .unwrap();
+        let hexsaly = &config.hexsaly;
+        assert!(hexsaly.style && hexsaly.menu && hexsaly.journal);
+        assert_eq!(hexsaly.backup, BackupPolicy::Bak);
+        assert_eq!(hexsaly.on_mismatch, MismatchPolicy::Skip);
+
+        let file = &hexsaly.files[1];
+        assert_eq!((file.title(), file.output()), ("b.bin", "b.bin"));
+        assert!(matches!(file.patches[0].locator, Locator::Offset(0x10)));
+        assert!(matches!(
+            &file.patches[0].action,
+            Action::Replace(Payload::Hex(bytes)) if bytes == &[0x90, 0x90]
+        ));
+        assert!(file.patches[0].expect.is_some());
+        assert!(matches!(file.patches[1].locator, Locator::Wildcard(_)));
+        assert!(matches!(file.patches[1].action, Action::Insert(_)));
+        assert_eq!(file.patches[1].position, Some(2));
+        assert_eq!(file.patches[1].occurrence, Occurrence::Nth(2));
+        assert_eq!(file.patches[2].address, Some(AddressKind::Rva));
+        assert!(matches!(
+            file.patches[2].action,
+            Action::Replace(Payload::Asm(_))
+        ));
+        assert!(matches!(
+            &file.patches[3].action,
+            Action::Replace(Payload::Hex(bytes)) if bytes == &[7, 0]
+        ));
+    }
+
+    #[test]
+    fn errors_name_the_field() {
+        let error = patch_error(json!({"offset": "0x10", "hex_replce": "90"}));
+        assert!(
+            error.starts_with("Hexsaly.files[1].patches[1]") && error.contains("hex_replce"),
+            "{}",
+            error
+        );
+        let error = patch_error(json!({"offset": 16, "hex_replace": "90"}));
+        assert!(
+            error.starts_with("Hexsaly.files[1].patches[1].offset"),
+            "{}",
+            error
+        );
+        let error = read(json!({"Hexsaly": {"files": [], "stlye": false}})).unwrap_err();
+        assert!(error.contains("stlye"), "{}", error);
+    }
+
+    #[test]
+    fn patches_need_one_locator_and_one_action() {
+        for (patch, message) in [
+            (json!({"hex_replace": "90"}), "exactly one of `offset`"),
+            (
+                json!({"offset": "0x0", "rva": "0x0", "hex_replace": "90"}),
+                "exactly one of `offset`",
+            ),
+            (json!({"offset": "0x0"}), "exactly one of `hex_replace`"),
+            (
+                json!({"offset": "0x0", "hex_replace": "90", "hex_insert": "90"}),
+                "exactly one of `hex_replace`",
+            ),
+            (
+                json!({"offset": "0x0", "hex_replace": ""}),
+                "hex string is empty",
+            ),
+        ] {
+            let error = patch_error(patch.clone());
+            assert!(error.contains(message), "{}: {}", patch, error);
+        }
+    }
+
+    #[test]
+    fn options_must_fit_the_locator() {
+        for (patch, message) in [
+            (
+                json!({"offset": "0x0", "position": 1, "hex_replace": "90"}),
+                "`position` is only valid together with `wildcard`",
+            ),
+            (
+                json!({"offset": "0x0", "occurrence": "all", "hex_replace": "90"}),
+                "`occurrence` is only valid together with `wildcard`",
+            ),
+            (
+                json!({"rva": "0x0", "section": ".text", "hex_replace": "90"}),
+                "`section` is only valid together with `wildcard`",
+            ),
+            (
+                json!({"offset": "0x0", "address": "va", "hex_replace": "90"}),
+                "`address` is only valid together with `method_name`",
+            ),
+            (
+                json!({"wildcard": "90", "occurrence": {"nth": 0}, "hex_replace": "90"}),
+                "counts from 1",
+            ),
+        ] {
+            let error = patch_error(patch.clone());
+            assert!(error.contains(message), "{}: {}", patch, error);
+        }
+    }
+
+    #[test]
+    fn backup_policies() {
+        let backup = |backup: serde_json::Value| {
+            read(json!({"Hexsaly": {"files": [], "backup": backup}})).map(|c| c.hexsaly.backup)
+        };
+        assert_eq!(backup(json!("none")), Ok(BackupPolicy::None));
+        assert_eq!(backup(json!("timestamped")), Ok(BackupPolicy::Timestamped));
+        assert_eq!(
+            backup(json!({"dir": {"path": "backups", "keep": 3}})),
+            Ok(BackupPolicy::Directory {
+                path: "backups".into(),
+                keep: Some(3)
+            })
+        );
+        assert!(backup(json!("daily")).is_err());
+    }
+}
@@ -0,0 +1,59 @@
+use crate::func::binary::Isa;
+use crate::func::hex::format_bytes;
+use capstone::arch::{arm, arm64, x86, BuildsCapstone};
+use capstone::Capstone;
+
+/// Longest instruction of any supported instruction set, read past a patch so the last
+/// instruction it touches decodes in full.
+pub const MAX_INSTRUCTION_LEN: usize = 15;
+
+/// Decodes the instructions of `code`, which starts at file offset `address`, until they cover
+/// its first `len` bytes. Bytes that do not decode are listed as `.byte`.
+pub fn disassemble(code: &[u8], address: u64, len: usize, isa: Isa) -> Result<Vec<String>, String> {
+    let cs = match isa {
+        Isa::X86 => Capstone::new().x86().mode(x86::ArchMode::Mode32).build(),
+        Isa::X86_64 => Capstone::new().x86().mode(x86::ArchMode::Mode64).build(),
+        Isa::Arm => Capstone::new().arm().mode(arm::ArchMode::Arm).build(),
+        Isa::Thumb => Capstone::new().arm().mode(arm::ArchMode::Thumb).build(),
+        Isa::Arm64 => Capstone::new().arm64().mode(arm64::ArchMode::Arm).build(),
+        Isa::Unknown => {
+            return Err("unknown instruction set; set the file's `arch` to disassemble".into())
+        }
+    }
+    .map_err(|e| e.to_string())?;
+    // Undecodable bytes are skipped one instruction slot at a time
+    let step = match isa {
+        Isa::X86 | Isa::X86_64 => 1,
+        Isa::Thumb => 2,
+        _ => 4,
+    };
+
+    let mut lines = Vec::new();
+    let mut at = 0;
+    while at < len.min(code.len()) {
+        let pc = address + at as u64;
+        let decoded = cs.disasm_count(&code[at..], pc, 1).ok();
+        match decoded.as_ref().and_then(|insns| insns.iter().next()) {
+            Some(insn) => {
+                let text = match insn.op_str() {
+                    Some(ops) if !ops.is_empty() => {
+                        format!("{} {}", insn.mnemonic().unwrap_or_default(), ops)
+                    }
+                    _ => insn.mnemonic().unwrap_or_default().to_string(),
+                };
+                lines.push(format!("0x{:X}: {}", pc, text));
+                at += insn.bytes().len();
+            }
+            None => {
+                let end = (at + step).min(code.len());
+                lines.push(format!(
+                    "0x{:X}: .byte {}",
+                    pc,
+                    format_bytes(&code[at..end])
+                ));
+                at = end;
+            }
+        }
+    }
+    Ok(lines)
+}
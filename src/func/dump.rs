@@ -0,0 +1,1043 @@
+use crate::func::atomic::write_atomic;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Most candidates listed when a method name is ambiguous.
+const MAX_CANDIDATES: usize = 10;
+/// Bumped whenever the cached layout of `Method` or `StringLiteral` changes.
+const CACHE_VERSION: u32 = 1;
+
+/// A method declared in an Il2CppDumper `dump.cs`, with the addresses from the comment above it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Method {
+    pub namespace: String,
+    /// Declaring type, with nested types joined by `.` (`Outer.Inner`).
+    pub class: String,
+    pub name: String,
+    pub return_type: String,
+    /// Parameter types without names or default values.
+    pub params: Vec<String>,
+    pub rva: Option<u64>,
+    pub offset: Option<u64>,
+    pub va: Option<u64>,
+}
+
+impl Method {
+    /// `Namespace.Class::Name`, without the namespace when it is empty.
+    pub fn qualified_name(&self) -> String {
+        if self.namespace.is_empty() {
+            format!("{}::{}", self.class, self.name)
+        } else {
+            format!("{}.{}::{}", self.namespace, self.class, self.name)
+        }
+    }
+
+    fn type_path(&self) -> String {
+        if self.namespace.is_empty() {
+            self.class.clone()
+        } else {
+            format!("{}.{}", self.namespace, self.class)
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({})", self.qualified_name(), self.params.join(", "))?;
+        match (self.offset, self.rva) {
+            (Some(offset), _) => write!(f, " [Offset: 0x{:X}]", offset),
+            (None, Some(rva)) => write!(f, " [RVA: 0x{:X}]", rva),
+            (None, None) => write!(f, " [no code]"),
+        }
+    }
+}
+
+/// A string literal from `stringliteral.json` or the `ScriptString` list of `script.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StringLiteral {
+    pub value: String,
+    pub rva: u64,
+}
+
+/// The methods and string literals of one or more Il2CppDumper outputs, looked up with
+/// `Namespace.Class::Method(int, string)`.
+#[derive(Debug, Clone, Default)]
+pub struct Dump {
+    pub methods: Vec<Method>,
+    pub strings: Vec<StringLiteral>,
+    by_name: HashMap<String, Vec<usize>>,
+    by_qualified: HashMap<String, Vec<usize>>,
+    by_value: HashMap<String, Vec<usize>>,
+}
+
+/// Size and modification time of a symbol source when its index was cached.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct SourceStamp {
+    path: String,
+    size: u64,
+    modified: u128,
+}
+
+impl SourceStamp {
+    fn of(path: &str) -> Result<Self, io::Error> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos());
+        Ok(SourceStamp {
+            path: path.to_string(),
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SymbolCache {
+    version: u32,
+    sources: Vec<SourceStamp>,
+    methods: Vec<Method>,
+    strings: Vec<StringLiteral>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ScriptJson {
+    #[serde(default)]
+    script_method: Vec<ScriptMethod>,
+    #[serde(default)]
+    script_string: Vec<ScriptString>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ScriptMethod {
+    address: u64,
+    name: String,
+    #[serde(default)]
+    signature: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ScriptString {
+    address: u64,
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct LiteralEntry {
+    value: String,
+    address: String,
+}
+
+/// How `Dump::load` uses the index of parsed symbols kept next to the first source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    Off,
+    /// Reuse an index that is up to date, but never write one.
+    ReadOnly,
+    ReadWrite,
+}
+
+impl Dump {
+    /// Loads, merges and indexes every source. Unless `mode` is `Off`, the parsed symbols are
+    /// read from `<first source>.hexsaly-index.json` while no source has changed size or mtime,
+    /// and with `ReadWrite` saved there after parsing.
+    pub fn load(paths: &[String], mode: CacheMode) -> Result<Self, io::Error> {
+        let Some(first) = paths.first().filter(|_| mode != CacheMode::Off) else {
+            let mut dump = Self::load_sources(paths)?;
+            dump.build_index();
+            return Ok(dump);
+        };
+        let cache_path = format!("{}.hexsaly-index.json", first);
+        let sources = paths
+            .iter()
+            .map(|path| SourceStamp::of(path))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let cached = fs::read(&cache_path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<SymbolCache>(&bytes).ok())
+            .filter(|cache| cache.version == CACHE_VERSION && cache.sources == sources);
+        let mut dump = match cached {
+            Some(cache) => Dump {
+                methods: cache.methods,
+                strings: cache.strings,
+                ..Dump::default()
+            },
+            None => {
+                let dump = Self::load_sources(paths)?;
+                let cache = SymbolCache {
+                    version: CACHE_VERSION,
+                    sources,
+                    methods: dump.methods,
+                    strings: dump.strings,
+                };
+                // A cache that cannot be written only costs the next run a reparse
+                if let (CacheMode::ReadWrite, Ok(json)) = (mode, serde_json::to_vec(&cache)) {
+                    let _ = write_atomic(Path::new(&cache_path), &json, None, false);
+                }
+                Dump {
+                    methods: cache.methods,
+                    strings: cache.strings,
+                    ..Dump::default()
+                }
+            }
+        };
+        dump.build_index();
+        Ok(dump)
+    }
+
+    /// Parses and merges every source, telling `dump.cs`, `script.json` and
+    /// `stringliteral.json` apart by their content.
+    fn load_sources(paths: &[String]) -> Result<Self, io::Error> {
+        let mut dump = Dump::default();
+        let mut seen_strings = HashSet::new();
+        for path in paths {
+            let bytes = fs::read(path)?;
+            let parsed = match bytes.iter().find(|b| !b.is_ascii_whitespace()) {
+                Some(b'{') => Self::parse_script_json(&bytes),
+                Some(b'[') => Self::parse_string_literals(&bytes),
+                _ => Ok(Self::parse(&String::from_utf8_lossy(&bytes))),
+            }
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid symbol file '{}': {}", path, e),
+                )
+            })?;
+            dump.methods.extend(parsed.methods);
+            // `script.json` repeats the literals of `stringliteral.json`
+            for literal in parsed.strings {
+                if seen_strings.insert(literal.clone()) {
+                    dump.strings.push(literal);
+                }
+            }
+        }
+        Ok(dump)
+    }
+
+    fn build_index(&mut self) {
+        for (i, method) in self.methods.iter().enumerate() {
+            self.by_name.entry(method.name.clone()).or_default().push(i);
+            // Keyed with and without namespace and generic arguments
+            let short = format!("{}::{}", method.class, method.name);
+            let mut keys = vec![method.qualified_name()];
+            if !method.namespace.is_empty() {
+                keys.push(short);
+            }
+            if method.class.contains('<') {
+                keys.extend(
+                    keys.iter()
+                        .map(|key| strip_generics(key))
+                        .collect::<Vec<_>>(),
+                );
+            }
+            for key in keys {
+                self.by_qualified.entry(key).or_default().push(i);
+            }
+        }
+        for (i, literal) in self.strings.iter().enumerate() {
+            self.by_value
+                .entry(literal.value.clone())
+                .or_default()
+                .push(i);
+        }
+    }
+
+    /// Reads `script.json`. Its method names are `Namespace.Class$$Method`, addresses are RVAs,
+    /// and parameter types are the C types of the generated signature.
+    pub fn parse_script_json(bytes: &[u8]) -> Result<Self, String> {
+        let script: ScriptJson = serde_json::from_slice(bytes).map_err(|e| e.to_string())?;
+        let methods = script
+            .script_method
+            .into_iter()
+            .map(|method| {
+                let (class, name) = method
+                    .name
+                    .rsplit_once("$$")
+                    .unwrap_or(("", method.name.as_str()));
+                let (return_type, params) = parse_signature(&method.signature);
+                Method {
+                    namespace: String::new(),
+                    class: class.to_string(),
+                    name: name.to_string(),
+                    return_type,
+                    params,
+                    rva: Some(method.address),
+                    offset: None,
+                    va: None,
+                }
+            })
+            .collect();
+        let strings = script
+            .script_string
+            .into_iter()
+            .map(|literal| StringLiteral {
+                value: literal.value,
+                rva: literal.address,
+            })
+            .collect();
+        Ok(Dump {
+            methods,
+            strings,
+            ..Dump::default()
+        })
+    }
+
+    /// Reads `stringliteral.json`, a list of `{"value": ..., "address": "0x..."}` entries.
+    pub fn parse_string_literals(bytes: &[u8]) -> Result<Self, String> {
+        let entries: Vec<LiteralEntry> =
+            serde_json::from_slice(bytes).map_err(|e| e.to_string())?;
+        let strings = entries
+            .into_iter()
+            .map(|entry| {
+                parse_address(&entry.address)
+                    .map(|rva| StringLiteral {
+                        value: entry.value,
+                        rva,
+                    })
+                    .ok_or_else(|| format!("invalid address '{}'", entry.address))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Dump {
+            strings,
+            ..Dump::default()
+        })
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let type_regex = Regex::new(
+            r"^(?:\[[^\]]*\]\s*)*(?:(?:public|private|protected|internal|static|sealed|abstract|readonly|unsafe|partial|ref)\s+)*(?:class|struct|interface|enum)\s+(.+?)(?:\s*:\s.*?)?\s*(?://.*)?$",
+        )
+        .expect("valid regex");
+
+        let mut methods = Vec::new();
+        let mut pending_namespace = String::new();
+        // Open types as (name, namespace, brace depth of their body)
+        let mut types: Vec<(String, String, usize)> = Vec::new();
+        let mut pending_type: Option<(String, String)> = None;
+        // Namespace of every type declared so far, by name
+        let mut declared_types: HashMap<String, String> = HashMap::new();
+        let mut addresses: Option<(Option<u64>, Option<u64>, Option<u64>)> = None;
+        let mut depth = 0usize;
+        let mut in_comment = false;
+
+        for line in text.lines() {
+            let line = line.trim();
+            if in_comment {
+                in_comment = !line.contains("*/");
+                continue;
+            }
+            if line.starts_with("/*") {
+                in_comment = !line.contains("*/");
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix("// Namespace:") {
+                pending_namespace = name.trim().to_string();
+                continue;
+            }
+            if line.starts_with("// RVA:") {
+                addresses = Some(parse_address_comment(line));
+                continue;
+            }
+            if line == "{" {
+                depth += 1;
+                if let Some((name, space)) = pending_type.take() {
+                    types.push((name, space, depth));
+                }
+                continue;
+            }
+            if line == "}" {
+                types.retain(|&(_, _, open)| open < depth);
+                depth = depth.saturating_sub(1);
+                continue;
+            }
+            if line.is_empty() || line.starts_with("//") || line.starts_with('[') {
+                continue;
+            }
+
+            if let Some((rva, offset, va)) = addresses.take() {
+                let Some((class, space)) = types.last().map(|(name, space, _)| (name, space))
+                else {
+                    continue;
+                };
+                if let Some((return_type, name, params)) = parse_method(line) {
+                    methods.push(Method {
+                        namespace: space.clone(),
+                        class: class.clone(),
+                        name,
+                        return_type,
+                        params,
+                        rva,
+                        offset,
+                        va,
+                    });
+                }
+                continue;
+            }
+
+            // Checking for a keyword first keeps the regex off most lines
+            let declares_type = ["class ", "struct ", "interface ", "enum "]
+                .iter()
+                .any(|keyword| line.contains(keyword));
+            if let Some(caps) = declares_type.then(|| type_regex.captures(line)).flatten() {
+                let declared = caps[1].trim().to_string();
+                // Nested types declared inside another type's braces inherit its path
+                let (name, space) = match types.last() {
+                    Some((outer, outer_space, _)) if !declared.contains('.') => {
+                        (format!("{}.{}", outer, declared), outer_space.clone())
+                    }
+                    _ => {
+                        // Il2CppDumper lists them at top level as `Outer.Inner` under an
+                        // empty namespace, so they take the namespace of `Outer`
+                        let namespace = std::mem::take(&mut pending_namespace);
+                        let outer_namespace = outer_type(&declared)
+                            .and_then(|outer| declared_types.get(outer))
+                            .filter(|_| namespace.is_empty());
+                        let space = outer_namespace.cloned().unwrap_or(namespace);
+                        (declared, space)
+                    }
+                };
+                declared_types.insert(name.clone(), space.clone());
+                pending_type = Some((name, space));
+            }
+        }
+
+        Dump {
+            methods,
+            ..Dump::default()
+        }
+    }
+
+    /// Finds the one method matching `query`, which may be a bare method name, `Class::Method`,
+    /// `Namespace.Class::Method` or any of those followed by a parameter list such as
+    /// `(int, string)`. Generic arguments on the class may be left out. A path naming a type
+    /// exactly, with or without its namespace, takes precedence over types it is only a
+    /// suffix of.
+    pub fn find(&self, query: &str) -> Result<&Method, String> {
+        let query = query.trim();
+        let (path, rest) = match query.rfind("::") {
+            Some(at) => (Some(query[..at].trim()), query[at + 2..].trim()),
+            None => (None, query),
+        };
+        let (name, params) = match rest.find('(') {
+            Some(at) => {
+                let inner = rest[at + 1..]
+                    .strip_suffix(')')
+                    .ok_or_else(|| format!("unterminated parameter list in '{}'", query))?;
+                (rest[..at].trim(), Some(split_params(inner)))
+            }
+            None => (rest, None),
+        };
+        let params: Option<Vec<String>> =
+            params.map(|list| list.iter().map(|param| normalize(param)).collect());
+
+        let exact = path.and_then(|path| self.by_qualified.get(&format!("{}::{}", path, name)));
+        let indices = exact
+            .or_else(|| self.by_name.get(name))
+            .map_or(&[][..], Vec::as_slice);
+        let mut candidates: Vec<&Method> = indices
+            .iter()
+            .map(|&i| &self.methods[i])
+            .filter(|method| path.is_none_or(|path| type_matches(method, path)))
+            .filter(|method| {
+                params.as_ref().is_none_or(|params| {
+                    method.params.len() == params.len()
+                        && method
+                            .params
+                            .iter()
+                            .zip(params)
+                            .all(|(actual, wanted)| normalize(actual) == *wanted)
+                })
+            })
+            .collect();
+        // The same method listed by several sources counts once
+        let mut seen = Vec::new();
+        candidates.retain(|method| match method.rva {
+            Some(rva) if seen.contains(&rva) => false,
+            Some(rva) => {
+                seen.push(rva);
+                true
+            }
+            None => true,
+        });
+
+        match candidates.as_slice() {
+            [method] => Ok(method),
+            [] => Err(format!("no method matching '{}' in the dump", query)),
+            _ => {
+                let mut listed: Vec<String> = candidates
+                    .iter()
+                    .take(MAX_CANDIDATES)
+                    .map(|method| format!("  {}", method))
+                    .collect();
+                if candidates.len() > MAX_CANDIDATES {
+                    listed.push(format!(
+                        "  ... and {} more",
+                        candidates.len() - MAX_CANDIDATES
+                    ));
+                }
+                Err(format!(
+                    "'{}' matches {} methods; qualify it with the class or parameter types:\n{}",
+                    query,
+                    candidates.len(),
+                    listed.join("\n")
+                ))
+            }
+        }
+    }
+}
+
+impl Dump {
+    /// Finds the one string literal whose value is exactly `value`.
+    pub fn find_string(&self, value: &str) -> Result<&StringLiteral, String> {
+        let candidates: Vec<&StringLiteral> = self
+            .by_value
+            .get(value)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .map(|&i| &self.strings[i])
+            .collect();
+        match candidates.as_slice() {
+            [literal] => Ok(literal),
+            [] => Err(format!("no string literal {:?} in the symbols", value)),
+            _ => {
+                let listed: Vec<String> = candidates
+                    .iter()
+                    .take(MAX_CANDIDATES)
+                    .map(|literal| format!("  RVA: 0x{:X}", literal.rva))
+                    .collect();
+                Err(format!(
+                    "string literal {:?} appears {} times:\n{}",
+                    value,
+                    candidates.len(),
+                    listed.join("\n")
+                ))
+            }
+        }
+    }
+}
+
+/// True when `path` names the method's type, optionally without its namespace or generic
+/// arguments.
+fn type_matches(method: &Method, path: &str) -> bool {
+    let full = method.type_path();
+    [full.clone(), strip_generics(&full)]
+        .iter()
+        .any(|candidate| {
+            candidate == path
+                || candidate
+                    .strip_suffix(path)
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+        })
+}
+
+/// `Outer` of a nested type name such as `Outer.Inner` or `Outer<T>.Inner`.
+fn outer_type(name: &str) -> Option<&str> {
+    let mut depth = 0usize;
+    for (i, c) in name.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth = depth.saturating_sub(1),
+            '.' if depth == 0 => return Some(&name[..i]),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn strip_generics(name: &str) -> String {
+    let mut stripped = String::with_capacity(name.len());
+    let mut depth = 0usize;
+    for c in name.chars() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => stripped.push(c),
+            _ => {}
+        }
+    }
+    stripped
+}
+
+/// Reads `// RVA: 0x1A2B Offset: 0x1A2B VA: 0x7F001A2B Slot: 4`; `-1` means no code.
+fn parse_address_comment(line: &str) -> (Option<u64>, Option<u64>, Option<u64>) {
+    let (mut rva, mut offset, mut va) = (None, None, None);
+    let mut words = line.split_whitespace();
+    while let Some(word) = words.next() {
+        let slot = match word {
+            "RVA:" => &mut rva,
+            "Offset:" => &mut offset,
+            "VA:" => &mut va,
+            _ => continue,
+        };
+        *slot = words.next().and_then(parse_address);
+    }
+    (rva, offset, va)
+}
+
+fn parse_address(text: &str) -> Option<u64> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))?;
+    u64::from_str_radix(digits, 16).ok()
+}
+
+/// Splits a `script.json` signature such as
+/// `void Player__Hit (Player_o* __this, int32_t damage, const MethodInfo* method);` into its
+/// return type and parameter types, leaving out `__this` and the trailing `MethodInfo`.
+fn parse_signature(signature: &str) -> (String, Vec<String>) {
+    let Some(open) = signature.find('(') else {
+        return (String::new(), Vec::new());
+    };
+    let head: Vec<&str> = signature[..open].split_whitespace().collect();
+    let return_type = head
+        .split_last()
+        .map(|(_, rest)| rest.join(" "))
+        .unwrap_or_default();
+    let close = matching_paren(signature, open).unwrap_or(signature.len());
+    let params = split_params(&signature[open + 1..close])
+        .iter()
+        .filter(|param| !param.ends_with("__this") && !param.contains("MethodInfo*"))
+        .map(|param| param_type(param))
+        .collect();
+    (return_type, params)
+}
+
+/// Splits `public static int Foo(int a, string b = "") { }` into its return type, name and
+/// parameter types.
+fn parse_method(line: &str) -> Option<(String, String, Vec<String>)> {
+    let open = line.find('(')?;
+    let close = matching_paren(line, open)?;
+    let head: Vec<&str> = line[..open].split_whitespace().collect();
+    let (name, rest) = head.split_last()?;
+    let return_type = rest
+        .iter()
+        .filter(|word| !is_modifier(word))
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let params = split_params(&line[open + 1..close])
+        .iter()
+        .map(|param| param_type(param))
+        .collect();
+    Some((return_type, name.to_string(), params))
+}
+
+fn matching_paren(line: &str, open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in line[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_modifier(word: &str) -> bool {
+    matches!(
+        word,
+        "public"
+            | "private"
+            | "protected"
+            | "internal"
+            | "static"
+            | "virtual"
+            | "override"
+            | "abstract"
+            | "sealed"
+            | "extern"
+            | "unsafe"
+            | "new"
+            | "async"
+            | "readonly"
+    )
+}
+
+/// Splits a parameter list on the commas that are not inside `<>`, `[]` or `()`.
+fn split_params(list: &str) -> Vec<String> {
+    let mut params = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    for c in list.chars() {
+        match c {
+            '<' | '[' | '(' => depth += 1,
+            '>' | ']' | ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                params.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() || !params.is_empty() {
+        params.push(current);
+    }
+    params.into_iter().map(|p| p.trim().to_string()).collect()
+}
+
+/// The type of a declared parameter: `ref int count = 0` becomes `ref int`.
+fn param_type(param: &str) -> String {
+    let declared = param.split(" = ").next().unwrap_or(param).trim();
+    let declared = declared.strip_prefix("params ").unwrap_or(declared);
+    match declared.rfind(char::is_whitespace) {
+        // The last word is the name unless it closes a generic or array type
+        Some(at) if !declared[at..].contains(['>', ']']) => declared[..at].trim().to_string(),
+        _ => declared.to_string(),
+    }
+}
+
+/// Removes whitespace so `Dictionary<string,int>` and `Dictionary<string, int>` compare equal.
+fn normalize(param: &str) -> String {
+    param.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func::fixtures::scratch_dir;
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+
+    const DUMP_CS: &str = r#"
+// Image 0: Assembly-CSharp.dll - 0
+
+// Namespace: Game
+public class Player : MonoBehaviour // TypeDefIndex: 1
+{
+	// Fields
+	private int health; // 0x18
+
+	// Methods
+
+	// RVA: 0x1000 Offset: 0x400 VA: 0x181001000
+	private void Update() { }
+
+	// RVA: 0x1010 Offset: 0x410 VA: 0x181001010
+	public void Hit(int damage) { }
+
+	// RVA: 0x1020 Offset: 0x420 VA: 0x181001020
+	public void Hit(float damage, ref bool killed) { }
+
+	// RVA: -1 Offset: -1
+	public abstract void Think();
+
+	// RVA: 0x1030 Offset: 0x430 VA: 0x181001030
+	public void Load(Dictionary<string, int> items, string name = "") { }
+}
+
+// Namespace: 
+public class Player.Inventory // TypeDefIndex: 2
+{
+	// RVA: 0x2000 Offset: 0x1400 VA: 0x181002000
+	public void Update() { }
+}
+
+// Namespace: Game.AI
+public sealed class Enemy // TypeDefIndex: 3
+{
+	// RVA: 0x3000 Offset: 0x2400 VA: 0x181003000
+	public virtual bool Think() { }
+
+	public struct State // TypeDefIndex: 4
+	{
+		// RVA: 0x3100 Offset: 0x2500 VA: 0x181003100
+		public void Reset() { }
+	}
+}
+
+// Namespace: Game
+public class Cache<T> // TypeDefIndex: 5
+{
+	// RVA: 0x4000 Offset: 0x3400 VA: 0x181004000
+	public T Get(int key) { }
+}
+"#;
+
+    fn dump() -> Dump {
+        let mut dump = Dump::parse(DUMP_CS);
+        dump.build_index();
+        dump
+    }
+
+    fn offset(dump: &Dump, query: &str) -> Result<Option<u64>, String> {
+        dump.find(query).map(|method| method.offset)
+    }
+
+    #[test]
+    fn methods_carry_their_type_and_addresses() {
+        let dump = dump();
+        let update = dump.find("Game.Player::Update").unwrap();
+        assert_eq!(update.namespace, "Game");
+        assert_eq!(update.class, "Player");
+        assert_eq!(update.return_type, "void");
+        assert_eq!(
+            (update.rva, update.offset, update.va),
+            (Some(0x1000), Some(0x400), Some(0x1_8100_1000))
+        );
+        let think = dump.find("Player::Think").unwrap();
+        assert_eq!((think.rva, think.offset), (None, None));
+        assert_eq!(
+            dump.find("Player::Load").unwrap().params,
+            ["Dictionary<string, int>", "string"]
+        );
+    }
+
+    #[test]
+    fn overloads_are_told_apart_by_parameters() {
+        let dump = dump();
+        assert!(dump
+            .find("Player::Hit")
+            .unwrap_err()
+            .contains("matches 2 methods"));
+        assert_eq!(offset(&dump, "Player::Hit(int)"), Ok(Some(0x410)));
+        assert_eq!(offset(&dump, "Hit(float, ref bool)"), Ok(Some(0x420)));
+        assert_eq!(
+            offset(&dump, "Load(Dictionary<string,int>, string)"),
+            Ok(Some(0x430))
+        );
+        assert!(dump.find("Player::Hit(long)").is_err());
+    }
+
+    #[test]
+    fn nested_types_keep_the_outer_namespace() {
+        let dump = dump();
+        assert_eq!(
+            offset(&dump, "Game.Player.Inventory::Update"),
+            Ok(Some(0x1400))
+        );
+        assert_eq!(offset(&dump, "Inventory::Update"), Ok(Some(0x1400)));
+        assert_eq!(
+            offset(&dump, "Game.AI.Enemy.State::Reset"),
+            Ok(Some(0x2500))
+        );
+        assert_eq!(offset(&dump, "Enemy.State::Reset"), Ok(Some(0x2500)));
+    }
+
+    #[test]
+    fn generic_arguments_are_optional() {
+        let dump = dump();
+        assert_eq!(offset(&dump, "Game.Cache<T>::Get"), Ok(Some(0x3400)));
+        assert_eq!(offset(&dump, "Cache::Get(int)"), Ok(Some(0x3400)));
+    }
+
+    #[test]
+    fn ambiguous_names_list_their_candidates() {
+        let dump = dump();
+        let error = dump.find("Update").unwrap_err();
+        assert!(error.contains("Game.Player::Update"), "{}", error);
+        assert!(error.contains("Game.Player.Inventory::Update"), "{}", error);
+        let error = dump.find("Think").unwrap_err();
+        assert!(error.contains("matches 2 methods"), "{}", error);
+        assert_eq!(offset(&dump, "AI.Enemy::Think"), Ok(Some(0x2400)));
+        // A path only names whole type segments
+        assert!(dump.find("ayer::Update").is_err());
+        assert!(dump.find("Missing::Update").is_err());
+    }
+
+    /// `dump.cs` written to a fresh directory, with the path of its cache file.
+    fn dump_on_disk(test: &str) -> (String, String) {
+        let path = scratch_dir(test).join("dump.cs");
+        fs::write(&path, DUMP_CS).unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let cache = format!("{}.hexsaly-index.json", path);
+        (path, cache)
+    }
+
+    /// Rewrites the cached offset of `Game.Player::Update`, so a load can tell whether the
+    /// cache was used.
+    fn tamper_cache(cache: &str) {
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&fs::read(cache).unwrap()).unwrap();
+        json["methods"][0]["offset"] = 0xDEAD.into();
+        fs::write(cache, serde_json::to_vec(&json).unwrap()).unwrap();
+    }
+
+    fn update_offset(paths: &[String], mode: CacheMode) -> Option<u64> {
+        Dump::load(paths, mode)
+            .unwrap()
+            .find("Game.Player::Update")
+            .unwrap()
+            .offset
+    }
+
+    #[test]
+    fn cached_symbols_match_a_fresh_parse() {
+        let (path, cache) = dump_on_disk("cache-match");
+        let paths = [path];
+        let fresh = Dump::load(&paths, CacheMode::Off).unwrap();
+        assert!(!Path::new(&cache).exists());
+
+        Dump::load(&paths, CacheMode::ReadWrite).unwrap();
+        assert!(Path::new(&cache).exists());
+        let cached = Dump::load(&paths, CacheMode::ReadOnly).unwrap();
+        for query in [
+            "Game.Player.Inventory::Update",
+            "Player::Hit(int)",
+            "Cache::Get",
+        ] {
+            assert_eq!(
+                cached.find(query).map(|m| m.offset),
+                fresh.find(query).map(|m| m.offset),
+                "{}",
+                query
+            );
+        }
+        assert_eq!(cached.methods.len(), fresh.methods.len());
+
+        tamper_cache(&cache);
+        assert_eq!(update_offset(&paths, CacheMode::ReadOnly), Some(0xDEAD));
+        assert_eq!(update_offset(&paths, CacheMode::Off), Some(0x400));
+    }
+
+    #[test]
+    fn read_only_loads_never_write_a_cache() {
+        let (path, cache) = dump_on_disk("cache-read-only");
+        assert_eq!(update_offset(&[path], CacheMode::ReadOnly), Some(0x400));
+        assert!(!Path::new(&cache).exists());
+    }
+
+    #[test]
+    fn changed_sources_invalidate_the_cache() {
+        let (path, cache) = dump_on_disk("cache-stale");
+        let paths = [path.clone()];
+        Dump::load(&paths, CacheMode::ReadWrite).unwrap();
+        tamper_cache(&cache);
+
+        // Same size, later modification time
+        let later = SystemTime::now() + Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(update_offset(&paths, CacheMode::ReadWrite), Some(0x400));
+
+        // The rewritten cache is used until the size changes
+        tamper_cache(&cache);
+        assert_eq!(update_offset(&paths, CacheMode::ReadOnly), Some(0xDEAD));
+        fs::write(&path, format!("{}\n", DUMP_CS)).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(update_offset(&paths, CacheMode::ReadOnly), Some(0x400));
+    }
+
+    #[test]
+    fn corrupt_cache_is_parsed_again() {
+        let (path, cache) = dump_on_disk("cache-corrupt");
+        let paths = [path];
+        fs::write(&cache, b"{\"version\": 1, \"sources\": [").unwrap();
+        assert_eq!(update_offset(&paths, CacheMode::ReadWrite), Some(0x400));
+        // Replaced by a valid cache
+        let json: serde_json::Value = serde_json::from_slice(&fs::read(&cache).unwrap()).unwrap();
+        assert_eq!(json["version"], CACHE_VERSION);
+    }
+
+    const SCRIPT_JSON: &str = r#"{
+        "ScriptMethod": [
+            {"Address": 4096, "Name": "Game.Player$$Hit",
+             "Signature": "void Game_Player__Hit (Game_Player_o* __this, int32_t damage, const MethodInfo* method);"},
+            {"Address": 4352, "Name": "Game.Player$$Hit",
+             "Signature": "void Game_Player__Hit (Game_Player_o* __this, float damage, const MethodInfo* method);"},
+            {"Address": 8192, "Name": "Game.Player$$get_Name", "Signature": ""}
+        ],
+        "ScriptString": [
+            {"Address": 12288, "Value": "Game Over"},
+            {"Address": 12296, "Value": "Retry"}
+        ]
+    }"#;
+
+    const STRING_LITERALS: &str = r#"[
+        {"value": "Game Over", "address": "0x3000"},
+        {"value": "Continue?", "address": "0x3010"},
+        {"value": "Retry", "address": "0x3020"}
+    ]"#;
+
+    #[test]
+    fn script_json_methods_and_strings_are_found() {
+        let mut dump = Dump::parse_script_json(SCRIPT_JSON.as_bytes()).unwrap();
+        dump.build_index();
+        assert_eq!(
+            dump.find("Game.Player::Hit(int32_t)").unwrap().rva,
+            Some(0x1000)
+        );
+        assert_eq!(
+            dump.find("Game.Player::Hit(float)").unwrap().rva,
+            Some(0x1100)
+        );
+        assert_eq!(dump.find("get_Name").unwrap().rva, Some(0x2000));
+        assert!(dump
+            .find("Game.Player::Hit")
+            .unwrap_err()
+            .contains("0x1100"));
+        assert_eq!(dump.find_string("Retry").unwrap().rva, 0x3008);
+    }
+
+    #[test]
+    fn string_literals_are_found_by_value() {
+        let mut dump = Dump::parse_string_literals(STRING_LITERALS.as_bytes()).unwrap();
+        dump.build_index();
+        assert_eq!(dump.find_string("Continue?").unwrap().rva, 0x3010);
+        assert!(dump.find_string("continue?").is_err());
+
+        let invalid = r#"[{"value": "Retry", "address": "3020h"}]"#;
+        let error = Dump::parse_string_literals(invalid.as_bytes()).unwrap_err();
+        assert!(error.contains("invalid address '3020h'"));
+    }
+
+    #[test]
+    fn sources_are_told_apart_by_content() {
+        let dir = scratch_dir("dump-sources");
+        let paths: Vec<String> = [
+            ("dump.cs", DUMP_CS),
+            ("script.json", SCRIPT_JSON),
+            ("stringliteral.json", STRING_LITERALS),
+        ]
+        .iter()
+        .map(|(name, text)| {
+            let path = dir.join(name);
+            fs::write(&path, text).unwrap();
+            path.to_string_lossy().into_owned()
+        })
+        .collect();
+        let dump = Dump::load(&paths, CacheMode::Off).unwrap();
+        assert_eq!(
+            dump.find("Game.Player::Update").unwrap().offset,
+            Some(0x400)
+        );
+        assert_eq!(
+            dump.find("Game.Player::Hit(int32_t)").unwrap().rva,
+            Some(0x1000)
+        );
+        // Literals of both JSON files, with the one they share at the same address listed once
+        assert_eq!(dump.find_string("Game Over").unwrap().rva, 0x3000);
+        assert_eq!(dump.find_string("Continue?").unwrap().rva, 0x3010);
+        assert!(dump
+            .find_string("Retry")
+            .unwrap_err()
+            .contains("appears 2 times"));
+    }
+}
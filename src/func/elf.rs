@@ -0,0 +1,212 @@
+use crate::func::binary::{Reader, Section, Segment};
+
+const PT_LOAD: u32 = 1;
+const SHT_NOBITS: u32 = 8;
+
+/// The parts of an ELF file needed to map addresses and sections to file offsets.
+#[derive(Debug, Clone)]
+pub struct Elf {
+    pub is_64: bool,
+    pub big_endian: bool,
+    /// `e_machine`, the target instruction set.
+    pub machine: u16,
+    pub segments: Vec<Segment>,
+    pub sections: Vec<Section>,
+}
+
+impl Elf {
+    pub fn is_elf(data: &[u8]) -> bool {
+        data.starts_with(b"\x7FELF")
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        if !Self::is_elf(data) {
+            return Err("not an ELF file".into());
+        }
+        let is_64 = match data.get(4) {
+            Some(1) => false,
+            Some(2) => true,
+            _ => return Err("unknown ELF class".into()),
+        };
+        let big_endian = match data.get(5) {
+            Some(1) => false,
+            Some(2) => true,
+            _ => return Err("unknown ELF byte order".into()),
+        };
+        let r = Reader::new(data, big_endian);
+        let machine = r.u16(0x12)?;
+
+        let (phoff, shoff, sizes_at) = if is_64 {
+            (r.u64(0x20)?, r.u64(0x28)?, 0x36)
+        } else {
+            (r.u32(0x1C)? as u64, r.u32(0x20)? as u64, 0x2A)
+        };
+        let phentsize = r.u16(sizes_at)? as u64;
+        let phnum = r.u16(sizes_at + 2)? as u64;
+        let shentsize = r.u16(sizes_at + 4)? as u64;
+        let mut shnum = r.u16(sizes_at + 6)? as u64;
+        let mut shstrndx = r.u16(sizes_at + 8)? as u64;
+
+        let mut segments = Vec::new();
+        for i in 0..phnum {
+            let at = table_entry(phoff, i, phentsize)?;
+            if r.u32(at)? != PT_LOAD {
+                continue;
+            }
+            let segment = if is_64 {
+                Segment {
+                    name: String::new(),
+                    offset: r.u64(at + 0x08)?,
+                    vaddr: r.u64(at + 0x10)?,
+                    file_size: r.u64(at + 0x20)?,
+                    mem_size: r.u64(at + 0x28)?,
+                }
+            } else {
+                Segment {
+                    name: String::new(),
+                    offset: r.u32(at + 0x04)? as u64,
+                    vaddr: r.u32(at + 0x08)? as u64,
+                    file_size: r.u32(at + 0x10)? as u64,
+                    mem_size: r.u32(at + 0x14)? as u64,
+                }
+            };
+            segments.push(segment);
+        }
+
+        let mut sections = Vec::new();
+        if shoff != 0 {
+            let header = |i: u64| -> Result<(u32, u32, u64, u64, u64, u32), String> {
+                let at = table_entry(shoff, i, shentsize)?;
+                if is_64 {
+                    Ok((
+                        r.u32(at)?,
+                        r.u32(at + 0x04)?,
+                        r.u64(at + 0x10)?,
+                        r.u64(at + 0x18)?,
+                        r.u64(at + 0x20)?,
+                        r.u32(at + 0x28)?,
+                    ))
+                } else {
+                    Ok((
+                        r.u32(at)?,
+                        r.u32(at + 0x04)?,
+                        r.u32(at + 0x0C)? as u64,
+                        r.u32(at + 0x10)? as u64,
+                        r.u32(at + 0x14)? as u64,
+                        r.u32(at + 0x18)?,
+                    ))
+                }
+            };
+
+            // Extended numbering keeps the real counts in the first section header
+            let (_, _, _, _, first_size, first_link) = header(0)?;
+            if shnum == 0 {
+                shnum = first_size;
+            }
+            if shstrndx == 0xFFFF {
+                shstrndx = first_link as u64;
+            }
+
+            let (_, _, _, strtab_offset, strtab_size, _) = header(shstrndx)?;
+            let strtab_offset = to_usize(strtab_offset)?;
+            let strtab_end = strtab_offset.saturating_add(to_usize(strtab_size)?);
+            for i in 0..shnum {
+                let (name, kind, addr, offset, size, _) = header(i)?;
+                if kind == SHT_NOBITS || size == 0 {
+                    continue;
+                }
+                let name_at = strtab_offset
+                    .checked_add(name as usize)
+                    .ok_or("section name is out of range")?;
+                sections.push(Section {
+                    name: r.c_str(name_at, strtab_end)?,
+                    offset,
+                    vaddr: addr,
+                    size,
+                });
+            }
+        }
+
+        Ok(Elf {
+            is_64,
+            big_endian,
+            machine,
+            segments,
+            sections,
+        })
+    }
+
+    /// Load address the image's RVAs are relative to: the lowest loadable page.
+    pub fn image_base(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|segment| segment.vaddr)
+            .min()
+            .unwrap_or(0)
+            & !0xFFF
+    }
+}
+
+/// File offset of entry `index` of the header table at `base`, failing on overflow.
+fn table_entry(base: u64, index: u64, entry_size: u64) -> Result<usize, String> {
+    index
+        .checked_mul(entry_size)
+        .and_then(|rel| base.checked_add(rel))
+        .ok_or_else(|| {
+            format!(
+                "header {} of the table at 0x{:X} is out of range",
+                index, base
+            )
+        })
+        .and_then(to_usize)
+}
+
+fn to_usize(value: u64) -> Result<usize, String> {
+    usize::try_from(value).map_err(|_| format!("value 0x{:X} is out of range", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func::fixtures::elf64;
+
+    #[test]
+    fn load_segments_and_sections() {
+        let data = elf64(
+            0x3000,
+            &[
+                (0, 0x40_0000, 0x1000, 0x1000),
+                (0x1000, 0x40_2000, 0x800, 0x2000),
+            ],
+            &[
+                (".text", 0x200, 0x40_0200, 0x100),
+                (".data", 0x1000, 0x40_2000, 0x800),
+            ],
+        );
+        let elf = Elf::parse(&data).unwrap();
+        assert!(elf.is_64 && !elf.big_endian);
+        assert_eq!(elf.machine, 62);
+        let segments: Vec<_> = elf
+            .segments
+            .iter()
+            .map(|s| (s.offset, s.vaddr, s.file_size, s.mem_size))
+            .collect();
+        assert_eq!(
+            segments,
+            [
+                (0, 0x40_0000, 0x1000, 0x1000),
+                (0x1000, 0x40_2000, 0x800, 0x2000)
+            ]
+        );
+        let names: Vec<_> = elf.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, [".text", ".data", ".shstrtab"]);
+        assert_eq!(elf.image_base(), 0x40_0000);
+    }
+
+    #[test]
+    fn truncated_headers_are_an_error() {
+        let data = elf64(0, &[(0, 0x40_0000, 0x100, 0x100)], &[]);
+        assert!(Elf::parse(&data[..0x50]).is_err());
+        assert!(Elf::parse(b"\x7FELF\x03\x01").is_err());
+    }
+}
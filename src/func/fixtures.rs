@@ -0,0 +1,250 @@
+//! Builders for the binaries and byte runs the unit tests patch.
+
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// An empty directory of its own under the system temp directory for the test `name`.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("hexsaly-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// `len` bytes of big-endian 16-bit counters, in which every 8 bytes occur only once.
+pub fn distinct(len: usize) -> Vec<u8> {
+    (0..len as u16 / 2).flat_map(u16::to_be_bytes).collect()
+}
+
+/// A little-endian x86_64 ELF64 image of at least `len` bytes with the given
+/// `(offset, vaddr, file_size, mem_size)` load segments and `(name, offset, vaddr, size)`
+/// sections. The headers sit at the start of the file, followed by the section name table.
+pub fn elf64(
+    len: usize,
+    segments: &[(u64, u64, u64, u64)],
+    sections: &[(&str, u64, u64, u64)],
+) -> Vec<u8> {
+    let phoff = 64;
+    let shoff = phoff + segments.len() * 56;
+    // Section 0 is the null section and the last one holds the names
+    let shnum = sections.len() + 2;
+    let strtab_at = shoff + shnum * 64;
+    let mut names = vec![0u8];
+    let mut name_at = Vec::new();
+    for (name, ..) in sections.iter().chain([&(".shstrtab", 0, 0, 0)]) {
+        name_at.push(names.len() as u32);
+        names.extend_from_slice(name.as_bytes());
+        names.push(0);
+    }
+
+    let mut data = vec![0u8; len.max(strtab_at + names.len())];
+    data[..7].copy_from_slice(b"\x7FELF\x02\x01\x01");
+    put(&mut data, 0x12, &62u16.to_le_bytes());
+    put(&mut data, 0x20, &(phoff as u64).to_le_bytes());
+    put(&mut data, 0x28, &(shoff as u64).to_le_bytes());
+    put(&mut data, 0x36, &56u16.to_le_bytes());
+    put(&mut data, 0x38, &(segments.len() as u16).to_le_bytes());
+    put(&mut data, 0x3A, &64u16.to_le_bytes());
+    put(&mut data, 0x3C, &(shnum as u16).to_le_bytes());
+    put(&mut data, 0x3E, &(shnum as u16 - 1).to_le_bytes());
+
+    for (i, &(offset, vaddr, file_size, mem_size)) in segments.iter().enumerate() {
+        let at = phoff + i * 56;
+        put(&mut data, at, &1u32.to_le_bytes());
+        put(&mut data, at + 0x08, &offset.to_le_bytes());
+        put(&mut data, at + 0x10, &vaddr.to_le_bytes());
+        put(&mut data, at + 0x20, &file_size.to_le_bytes());
+        put(&mut data, at + 0x28, &mem_size.to_le_bytes());
+    }
+    let strtab = (".shstrtab", strtab_at as u64, 0, names.len() as u64);
+    for (i, &(_, offset, vaddr, size)) in sections.iter().chain([&strtab]).enumerate() {
+        let at = shoff + (i + 1) * 64;
+        put(&mut data, at, &name_at[i].to_le_bytes());
+        // SHT_PROGBITS, or SHT_STRTAB for the names
+        let kind: u32 = if i == sections.len() { 3 } else { 1 };
+        put(&mut data, at + 0x04, &kind.to_le_bytes());
+        put(&mut data, at + 0x10, &vaddr.to_le_bytes());
+        put(&mut data, at + 0x18, &offset.to_le_bytes());
+        put(&mut data, at + 0x20, &size.to_le_bytes());
+    }
+    put(&mut data, strtab_at, &names);
+    data
+}
+
+/// A PE32+ image for x86_64 of at least `len` bytes loaded at `image_base`, with 0x400 bytes
+/// of headers and the given `(name, rva, virtual_size, raw_offset, raw_size)` sections.
+pub fn pe64(len: usize, image_base: u64, sections: &[(&str, u32, u32, u32, u32)]) -> Vec<u8> {
+    let coff = 0x84;
+    let optional = coff + 20;
+    let optional_size = 0xF0;
+    let mut data = vec![0u8; len.max(0x400)];
+    put(&mut data, 0, b"MZ");
+    put(&mut data, 0x3C, &0x80u32.to_le_bytes());
+    put(&mut data, 0x80, b"PE\0\0");
+    put(&mut data, coff, &0x8664u16.to_le_bytes());
+    put(&mut data, coff + 2, &(sections.len() as u16).to_le_bytes());
+    put(&mut data, coff + 16, &(optional_size as u16).to_le_bytes());
+    put(&mut data, optional, &0x20Bu16.to_le_bytes());
+    put(&mut data, optional + 24, &image_base.to_le_bytes());
+    put(&mut data, optional + 60, &0x400u32.to_le_bytes());
+    for (i, &(name, rva, virtual_size, raw_offset, raw_size)) in sections.iter().enumerate() {
+        let at = optional + optional_size + i * 40;
+        put(&mut data, at, name.as_bytes());
+        put(&mut data, at + 8, &virtual_size.to_le_bytes());
+        put(&mut data, at + 12, &rva.to_le_bytes());
+        put(&mut data, at + 16, &raw_size.to_le_bytes());
+        put(&mut data, at + 20, &raw_offset.to_le_bytes());
+    }
+    data
+}
+
+/// A little-endian 64-bit Mach-O image of at least `len` bytes for `cpu_type`, with the given
+/// `(name, vaddr, vm_size, file_offset, file_size)` segments. Each segment with file data holds
+/// one `__data` section covering all of it.
+pub fn macho64(len: usize, cpu_type: u32, segments: &[(&str, u64, u64, u64, u64)]) -> Vec<u8> {
+    let mut data = vec![0u8; len.max(32 + segments.len() * (72 + 80))];
+    put(&mut data, 0, &0xFEED_FACFu32.to_le_bytes());
+    put(&mut data, 4, &cpu_type.to_le_bytes());
+    put(&mut data, 16, &(segments.len() as u32).to_le_bytes());
+    let mut at = 32;
+    for &(name, vaddr, vm_size, file_offset, file_size) in segments {
+        let section_count = u32::from(file_size != 0);
+        let size = 72 + 80 * section_count;
+        put(&mut data, at, &0x19u32.to_le_bytes());
+        put(&mut data, at + 4, &size.to_le_bytes());
+        put(&mut data, at + 8, name.as_bytes());
+        put(&mut data, at + 24, &vaddr.to_le_bytes());
+        put(&mut data, at + 32, &vm_size.to_le_bytes());
+        put(&mut data, at + 40, &file_offset.to_le_bytes());
+        put(&mut data, at + 48, &file_size.to_le_bytes());
+        put(&mut data, at + 64, &section_count.to_le_bytes());
+        if section_count != 0 {
+            let section = at + 72;
+            put(&mut data, section, b"__data");
+            put(&mut data, section + 16, name.as_bytes());
+            put(&mut data, section + 32, &vaddr.to_le_bytes());
+            put(&mut data, section + 40, &file_size.to_le_bytes());
+            put(&mut data, section + 48, &(file_offset as u32).to_le_bytes());
+        }
+        at += size as usize;
+    }
+    data
+}
+
+/// A universal binary of `(cpu_type, image)` slices, each starting on a 4096-byte boundary
+/// as `lipo` aligns them.
+pub fn fat(slices: &[(u32, &[u8])]) -> Vec<u8> {
+    let mut data = vec![0u8; 8];
+    put(&mut data, 0, &0xCAFE_BABEu32.to_be_bytes());
+    put(&mut data, 4, &(slices.len() as u32).to_be_bytes());
+    let mut offset = (8 + slices.len() * 20).next_multiple_of(4096);
+    for &(cpu_type, image) in slices {
+        for field in [cpu_type, 0, offset as u32, image.len() as u32, 12] {
+            data.extend_from_slice(&field.to_be_bytes());
+        }
+        offset = (offset + image.len()).next_multiple_of(4096);
+    }
+    for &(_, image) in slices {
+        data.resize(data.len().next_multiple_of(4096), 0);
+        data.extend_from_slice(image);
+    }
+    data
+}
+
+/// One member of an archive built by `zip`.
+pub struct ZipMember<'a> {
+    pub name: &'a str,
+    pub data: &'a [u8],
+    pub deflated: bool,
+    /// Leave the sizes out of the local header and write them in a signed data descriptor.
+    pub descriptor: bool,
+}
+
+impl<'a> ZipMember<'a> {
+    pub fn stored(name: &'a str, data: &'a [u8]) -> Self {
+        ZipMember {
+            name,
+            data,
+            deflated: false,
+            descriptor: false,
+        }
+    }
+
+    pub fn deflated(name: &'a str, data: &'a [u8]) -> Self {
+        ZipMember {
+            deflated: true,
+            ..ZipMember::stored(name, data)
+        }
+    }
+}
+
+/// A ZIP archive of `members` without any alignment, as a plain `zip` tool writes it.
+pub fn zip(members: &[ZipMember]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+    for member in members {
+        let body = if member.deflated {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(member.data).unwrap();
+            encoder.finish().unwrap()
+        } else {
+            member.data.to_vec()
+        };
+        let method: u16 = if member.deflated { 8 } else { 0 };
+        let flags: u16 = if member.descriptor { 1 << 3 } else { 0 };
+        let mut sizes = Vec::new();
+        sizes.extend_from_slice(&crc32fast::hash(member.data).to_le_bytes());
+        sizes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        sizes.extend_from_slice(&(member.data.len() as u32).to_le_bytes());
+        let offset = out.len() as u32;
+
+        out.extend_from_slice(&0x0403_4B50u32.to_le_bytes());
+        out.extend_from_slice(&20u16.to_le_bytes());
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&method.to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        if member.descriptor {
+            out.extend_from_slice(&[0; 12]);
+        } else {
+            out.extend_from_slice(&sizes);
+        }
+        out.extend_from_slice(&(member.name.len() as u16).to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(member.name.as_bytes());
+        out.extend_from_slice(&body);
+        if member.descriptor {
+            out.extend_from_slice(&0x0807_4B50u32.to_le_bytes());
+            out.extend_from_slice(&sizes);
+        }
+
+        central.extend_from_slice(&0x0201_4B50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes());
+        central.extend_from_slice(&flags.to_le_bytes());
+        central.extend_from_slice(&method.to_le_bytes());
+        central.extend_from_slice(&[0; 4]);
+        central.extend_from_slice(&sizes);
+        central.extend_from_slice(&(member.name.len() as u16).to_le_bytes());
+        central.extend_from_slice(&[0; 12]);
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(member.name.as_bytes());
+    }
+
+    let directory_offset = out.len() as u32;
+    out.extend_from_slice(&central);
+    out.extend_from_slice(&0x0605_4B50u32.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&(members.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(members.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    out.extend_from_slice(&directory_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out
+}
+
+fn put(data: &mut [u8], at: usize, bytes: &[u8]) {
+    data[at..at + bytes.len()].copy_from_slice(bytes);
+}
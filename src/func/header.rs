@@ -1,18 +1,11 @@
+use crate::func::config::{Config, FileEntry};
 use colored::*;
 use inquire::{Confirm, Select};
-use serde_json::{self, Value};
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufReader, Write};
 use std::path::Path;
-pub fn display_menu(files: &[Value], default_index: Option<usize>) -> Result<usize, io::Error> {
-    let options: Vec<String> = files
-        .iter()
-        .map(|file_config| {
-            let input = file_config["input"].as_str().unwrap_or("Unknown");
-            let title = file_config["title"].as_str().unwrap_or(input);
-            title.to_string()
-        })
-        .collect();
+pub fn display_menu(files: &[FileEntry], default_index: Option<usize>) -> Result<usize, io::Error> {
+    let options: Vec<String> = files.iter().map(|file| file.title().to_string()).collect();
 
     let mut select = Select::new("Select a file to patch:", options).with_vim_mode(true);
 
@@ -30,9 +23,7 @@ pub fn display_menu(files: &[Value], default_index: Option<usize>) -> Result<usi
     }
 }
 
-pub fn read_config(
-    config_path: &Path,
-) -> Result<(Vec<Value>, bool, bool), Box<dyn std::error::Error>> {
+pub fn read_config(config_path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
     let config_metadata = fs::metadata(config_path)?;
     if config_metadata.len() > 10 * 1024 * 1024 {
         return Err(Box::new(io::Error::new(
@@ -43,26 +34,15 @@ pub fn read_config(
 
     let file = File::open(config_path)?;
     let reader = BufReader::new(file);
-    let config: Value = serde_json::from_reader(reader)?;
-
-    if !config.is_object()
-        || !config["Hexsaly"].is_object()
-        || !config["Hexsaly"]["files"].is_array()
-    {
-        return Err(Box::new(io::Error::new(
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let config: Config = serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+        io::Error::new(
             io::ErrorKind::InvalidData,
-            "Invalid config file structure",
-        )));
-    }
-
-    let files = config["Hexsaly"]["files"]
-        .as_array()
-        .ok_or("Missing files in config")?
-        .clone();
-    let log_style = config["Hexsaly"]["style"].as_bool().unwrap_or(true);
-    let use_menu = config["Hexsaly"]["menu"].as_bool().unwrap_or(true);
+            format!("Invalid config at {}: {}", e.path(), e.inner()),
+        )
+    })?;
 
-    Ok((files, log_style, use_menu))
+    Ok(config)
 }
 
 pub fn write_example_config() -> Result<(), Box<dyn std::error::Error>> {
@@ -126,34 +106,3 @@ pub fn write_example_config() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
-
-pub fn validate_patch_structure(patch: &Value, log_style: bool) -> bool {
-    // Count the number of read methods (offset, wildcard, method_name)
-    let read_count = [
-        patch.get("offset").is_some(),
-        patch.get("wildcard").is_some(),
-        patch.get("method_name").is_some(),
-    ]
-    .iter()
-    .filter(|&&x| x)
-    .count();
-
-    // Count the number of hex methods (hex_replace, hex_insert)
-    let hex_count = [
-        patch.get("hex_replace").is_some(),
-        patch.get("hex_insert").is_some(),
-    ]
-    .iter()
-    .filter(|&&x| x)
-    .count();
-
-    if read_count != 1 || hex_count != 1 {
-        if log_style {
-            println!("{}","[ERROR] Invalid patch structure. Must have exactly two things: offset/wildcard/method_name and hex_replace/hex_insert.".red());
-        } else {
-            println!("{}","Error: Invalid patch structure. Must have exactly two things: offset/wildcard/method_name and hex_replace/hex_insert.".red());
-        }
-        return false;
-    }
-    true
-}
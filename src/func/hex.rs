@@ -0,0 +1,34 @@
+pub fn parse_bytes(hex: &str) -> Result<Vec<u8>, String> {
+    hex.split_whitespace()
+        .map(|s| {
+            if s.is_empty() || s.len() > 2 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(format!("invalid hex byte '{}'", s));
+            }
+            Ok(u8::from_str_radix(s, 16).unwrap())
+        })
+        .collect()
+}
+
+pub fn format_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn parse_address(address: &str) -> Result<u64, String> {
+    let trimmed = address.trim();
+    let digits = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+        .unwrap_or(trimmed);
+    u64::from_str_radix(digits, 16).map_err(|_| format!("invalid hex address '{}'", address))
+}
+
+pub fn parse_offset(offset: &str) -> Result<usize, String> {
+    parse_address(offset)
+        .ok()
+        .and_then(|offset| usize::try_from(offset).ok())
+        .ok_or_else(|| format!("invalid hex offset '{}'", offset))
+}
@@ -16,6 +16,107 @@ pub fn log_pattern_found(pattern: &str, log_style: bool) {
     }
 }
 
+pub fn log_pattern_matches(pattern: &str, offsets: &[usize], log_style: bool) {
+    let listed = offsets
+        .iter()
+        .map(|offset| format!("0x{:X}", offset))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if log_style {
+        println!(
+            "{}",
+            format!(
+                "[FOUND] {} match(es) for pattern: {} at {}",
+                offsets.len(),
+                pattern.blue(),
+                listed
+            )
+            .green()
+        );
+    } else {
+        println!(
+            "{}",
+            format!(
+                "Found {} match(es) for {} at {}",
+                offsets.len(),
+                pattern,
+                listed
+            )
+            .green()
+        );
+    }
+}
+
+pub fn log_address_resolved(kind: &str, address: u64, offset: usize, log_style: bool) {
+    if log_style {
+        println!(
+            "{}",
+            format!(
+                "[FOUND] {} 0x{:X} at file offset 0x{:X}",
+                kind, address, offset
+            )
+            .green()
+        );
+    } else {
+        println!(
+            "{}",
+            format!("{} 0x{:X} is at Offset: 0x{:X}", kind, address, offset).green()
+        );
+    }
+}
+
+pub fn log_checksum_updated(old: u32, new: u32, log_style: bool) {
+    let message = if old == new {
+        format!("PE checksum 0x{:08X} is already correct", old)
+    } else {
+        format!("PE checksum updated: 0x{:08X} -> 0x{:08X}", old, new)
+    };
+    if log_style {
+        println!("{}", format!("[CHECKSUM] {}", message).cyan());
+    } else {
+        println!("{}", message.cyan());
+    }
+}
+
+pub fn log_slice_selected(arch: &str, offset: usize, size: usize, log_style: bool) {
+    if log_style {
+        println!(
+            "{}",
+            format!(
+                "[SLICE] Patching {} slice at 0x{:X} ({} bytes)",
+                arch, offset, size
+            )
+            .cyan()
+        );
+    } else {
+        println!(
+            "{}",
+            format!("Patching {} slice at Offset: 0x{:X}", arch, offset).cyan()
+        );
+    }
+}
+
+pub fn log_signature_invalidated(log_style: bool) {
+    let message = "The code signature is no longer valid; re-sign the binary (e.g. codesign -f -s -) before running it";
+    if log_style {
+        println!("{}", format!("[WARN] {}", message).yellow());
+    } else {
+        println!("{}", message.yellow());
+    }
+}
+
+pub fn log_archive_unsigned(archive: &str, log_style: bool) {
+    let message = format!(
+        "{} was rewritten without its signature; re-sign it (e.g. apksigner sign) before installing",
+        archive
+    );
+    if log_style {
+        println!("{}", format!("[WARN] {}", message).yellow());
+    } else {
+        println!("{}", message.yellow());
+    }
+}
+
 pub fn log_method_found(method_name: &str, offset: usize, log_style: bool) {
     if log_style {
         println!(
@@ -30,6 +131,20 @@ pub fn log_method_found(method_name: &str, offset: usize, log_style: bool) {
     }
 }
 
+pub fn log_string_literal_found(value: &str, offset: usize, log_style: bool) {
+    if log_style {
+        println!(
+            "{}",
+            format!("[FOUND] String literal: {}", format!("{:?}", value).blue()).green()
+        );
+    } else {
+        println!(
+            "{}",
+            format!("Found string {:?} at Offset: 0x{:X}", value, offset).green()
+        );
+    }
+}
+
 pub fn log_no_offset_found(method_name: &str, log_style: bool) {
     if log_style {
         println!(
@@ -49,6 +164,36 @@ pub fn log_patch_action(action: &str, hex: &str, log_style: bool) {
     }
 }
 
+pub fn log_disasm(before: &[String], after: &[String], log_style: bool) {
+    if log_style {
+        println!("{}", "[DISASM] Before:".blue());
+        for line in before {
+            println!("    {}", line.red());
+        }
+        println!("{}", "[DISASM] After:".blue());
+        for line in after {
+            println!("    {}", line.green());
+        }
+    } else {
+        println!("Before:");
+        for line in before {
+            println!("    {}", line);
+        }
+        println!("After:");
+        for line in after {
+            println!("    {}", line);
+        }
+    }
+}
+
+pub fn log_disasm_unavailable(reason: &str, log_style: bool) {
+    if log_style {
+        println!("{}", format!("[DISASM] {}", reason).yellow());
+    } else {
+        println!("{}", format!("Cannot disassemble: {}", reason).yellow());
+    }
+}
+
 pub fn log_patch_skip(item: &str, reason: &str, log_style: bool) {
     if log_style {
         println!(
@@ -90,3 +235,189 @@ pub fn log_patch_done(output: &str, log_style: bool) {
         println!("{}", format!("File Save as: {}", output).green());
     }
 }
+
+pub fn log_patchfile_exported(path: &str, format: &str, changes: usize, log_style: bool) {
+    if log_style {
+        println!(
+            "{}",
+            format!(
+                "[DONE] {} patch with {} change(s) saved as: {}",
+                format, changes, path
+            )
+            .green()
+        );
+    } else {
+        println!(
+            "{}",
+            format!(
+                "{} patch with {} change(s) saved as: {}",
+                format, changes, path
+            )
+            .green()
+        );
+    }
+}
+
+pub fn log_patchfile_applied(path: &str, format: &str, log_style: bool) {
+    if log_style {
+        println!(
+            "{}",
+            format!("[PATCH] Applied {} patch: {}", format, path.blue()).yellow()
+        );
+    } else {
+        println!("{}", format!("Applied {} patch: {}", format, path).yellow());
+    }
+}
+
+pub fn log_port_result(tag: &str, index: usize, locator: &str, detail: &str, log_style: bool) {
+    let line = format!("#{} {}: {}", index, locator, detail);
+    let line = match tag {
+        "FAILED" => line.red(),
+        "REVIEW" => line.yellow(),
+        _ => line.green(),
+    };
+    if log_style {
+        println!("[{}] {}", tag, line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+pub fn log_port_done(config: &str, report: &str, flagged: usize, log_style: bool) {
+    let message = format!(
+        "Ported config saved as: {} ({} patch(es) to check in {})",
+        config, flagged, report
+    );
+    if log_style {
+        println!("{}", format!("[DONE] {}", message).green());
+    } else {
+        println!("{}", message.green());
+    }
+}
+
+pub fn log_signature(pattern: &str, position: usize, offset: usize, log_style: bool) {
+    let snippet = if position == 0 {
+        format!("\"wildcard\": \"{}\"", pattern)
+    } else {
+        format!("\"wildcard\": \"{}\", \"position\": {}", pattern, position)
+    };
+    if log_style {
+        println!(
+            "{}",
+            format!(
+                "[SIGNATURE] Unique at offset 0x{:X}: {}",
+                offset,
+                pattern.blue()
+            )
+            .green()
+        );
+        println!("{}", format!("[POSITION] {}", position).cyan());
+        println!("{}", snippet);
+    } else {
+        println!(
+            "{}",
+            format!(
+                "Signature for Offset 0x{:X} (position {}):",
+                offset, position
+            )
+            .green()
+        );
+        println!("{}", snippet);
+    }
+}
+
+pub fn log_signature_fallback(offset: usize, reason: &str, log_style: bool) {
+    let message = format!("{}; using offset 0x{:X} instead", reason, offset);
+    if log_style {
+        println!("{}", format!("[WARN] {}", message).yellow());
+    } else {
+        println!("{}", message.yellow());
+    }
+}
+
+pub fn log_diff_done(config: &str, patches: usize, log_style: bool) {
+    if log_style {
+        println!(
+            "{}",
+            format!(
+                "[DONE] Config with {} patch(es) saved as: {}",
+                patches, config
+            )
+            .green()
+        );
+    } else {
+        println!(
+            "{}",
+            format!("Config with {} patch(es) saved as: {}", patches, config).green()
+        );
+    }
+}
+
+pub fn log_dry_run_change(offset: usize, old: &str, new: &str, log_style: bool) {
+    if log_style {
+        println!(
+            "{}",
+            format!("[DRY-RUN] 0x{:X}: {} -> {}", offset, old.red(), new.green()).cyan()
+        );
+    } else {
+        println!(
+            "{}",
+            format!("Would write at Offset 0x{:X}: {} -> {}", offset, old, new).cyan()
+        );
+    }
+}
+
+pub fn log_dry_run_done(input: &str, changes: usize, log_style: bool) {
+    if log_style {
+        println!(
+            "{}",
+            format!(
+                "[DONE] Dry run of {}: {} change(s), nothing written",
+                input, changes
+            )
+            .green()
+        );
+    } else {
+        println!(
+            "{}",
+            format!(
+                "Dry run of {}: {} change(s), nothing written",
+                input, changes
+            )
+            .green()
+        );
+    }
+}
+
+pub fn log_backup_created(backup: &str, log_style: bool) {
+    if log_style {
+        println!(
+            "{}",
+            format!("[BACKUP] Original saved as: {}", backup).cyan()
+        );
+    } else {
+        println!("{}", format!("Backup saved as: {}", backup).cyan());
+    }
+}
+
+pub fn log_backup_entry(number: usize, backup: &str, log_style: bool) {
+    if log_style {
+        println!(
+            "{}",
+            format!("[BACKUP] {}: {}", number, backup.blue()).cyan()
+        );
+    } else {
+        println!("{}", format!("{}. {}", number, backup).cyan());
+    }
+}
+
+pub fn log_backup_restored(backup: &str, file: &str, log_style: bool) {
+    if log_style {
+        println!(
+            "{}",
+            format!("[DONE] Restored {} from {}", file, backup).green()
+        );
+    } else {
+        println!("{}", format!("Restored {} from {}", file, backup).green());
+    }
+}
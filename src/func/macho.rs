@@ -0,0 +1,282 @@
+use crate::func::binary::{Reader, Section, Segment};
+use std::ops::Range;
+
+const MH_MAGIC: u32 = 0xFEED_FACE;
+const MH_MAGIC_64: u32 = 0xFEED_FACF;
+const FAT_MAGIC: u32 = 0xCAFE_BABE;
+const FAT_MAGIC_64: u32 = 0xCAFE_BABF;
+/// Java class files share `FAT_MAGIC`; their version field is far above any real arch count.
+const MAX_FAT_ARCHS: u32 = 32;
+
+const LC_SEGMENT: u32 = 0x1;
+const LC_SEGMENT_64: u32 = 0x19;
+const LC_CODE_SIGNATURE: u32 = 0x1D;
+
+const CPU_ARCH_ABI64: u32 = 0x0100_0000;
+const CPU_ARCH_ABI64_32: u32 = 0x0200_0000;
+const CPU_TYPE_X86: u32 = 7;
+const CPU_TYPE_ARM: u32 = 12;
+const CPU_TYPE_POWERPC: u32 = 18;
+const CPU_SUBTYPE_MASK: u32 = 0x00FF_FFFF;
+
+/// Section types (`flags & 0xFF`) that occupy no space in the file.
+const ZEROFILL_TYPES: [u32; 3] = [0x1, 0xC, 0x12];
+
+/// One architecture slice of a universal (fat) binary.
+#[derive(Debug, Clone)]
+pub struct FatArch {
+    pub name: String,
+    pub offset: usize,
+    pub size: usize,
+}
+
+impl FatArch {
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.size
+    }
+}
+
+/// The parts of a thin Mach-O image needed to map VM addresses and sections to file offsets.
+#[derive(Debug, Clone)]
+pub struct MachO {
+    pub is_64: bool,
+    pub arch: String,
+    pub segments: Vec<Segment>,
+    pub sections: Vec<Section>,
+    /// File range of the `LC_CODE_SIGNATURE` blob, if the image is signed.
+    pub code_signature: Option<Range<u64>>,
+}
+
+impl MachO {
+    pub fn is_macho(data: &[u8]) -> bool {
+        magic(data).is_some()
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let (is_64, big_endian) = magic(data).ok_or_else(|| String::from("not a Mach-O file"))?;
+        let r = Reader::new(data, big_endian);
+        let arch = arch_name(r.u32(4)?, r.u32(8)?);
+        let command_count = r.u32(16)?;
+        let mut at = if is_64 { 32 } else { 28 };
+
+        let mut segments = Vec::new();
+        let mut sections = Vec::new();
+        let mut code_signature = None;
+        for _ in 0..command_count {
+            let command = r.u32(at)?;
+            let command_size = r.u32(at + 4)? as usize;
+            if command_size < 8 {
+                return Err(format!("malformed load command at offset 0x{:X}", at));
+            }
+
+            match command {
+                LC_SEGMENT_64 | LC_SEGMENT => {
+                    let wide = command == LC_SEGMENT_64;
+                    let word = |offset: usize| -> Result<u64, String> {
+                        if wide {
+                            r.u64(offset)
+                        } else {
+                            r.u32(offset).map(u64::from)
+                        }
+                    };
+                    let (width, section_start, section_size) =
+                        if wide { (8, 72, 80) } else { (4, 56, 68) };
+                    let segment_name = r.c_str(at + 8, at + 24)?;
+                    segments.push(Segment {
+                        name: segment_name.clone(),
+                        vaddr: word(at + 24)?,
+                        mem_size: word(at + 24 + width)?,
+                        offset: word(at + 24 + 2 * width)?,
+                        file_size: word(at + 24 + 3 * width)?,
+                    });
+
+                    let section_count = r.u32(at + section_start - 8)? as usize;
+                    for i in 0..section_count {
+                        let s = i
+                            .checked_mul(section_size)
+                            .and_then(|rel| rel.checked_add(at + section_start))
+                            .ok_or("section header is out of range")?;
+                        let name = r.c_str(s, s + 16)?;
+                        let vaddr = word(s + 32)?;
+                        let size = word(s + 32 + width)?;
+                        let offset = r.u32(s + 32 + 2 * width)? as u64;
+                        let flags = r.u32(s + 32 + 2 * width + 16)?;
+                        if ZEROFILL_TYPES.contains(&(flags & 0xFF)) || size == 0 {
+                            continue;
+                        }
+                        sections.push(Section {
+                            name: format!("{},{}", segment_name, name),
+                            offset,
+                            vaddr,
+                            size,
+                        });
+                    }
+                }
+                LC_CODE_SIGNATURE => {
+                    let offset = r.u32(at + 8)? as u64;
+                    code_signature = Some(offset..offset + r.u32(at + 12)? as u64);
+                }
+                _ => {}
+            }
+            at = at
+                .checked_add(command_size)
+                .ok_or_else(|| format!("load command at offset 0x{:X} is out of range", at))?;
+        }
+
+        Ok(MachO {
+            is_64,
+            arch,
+            segments,
+            sections,
+            code_signature,
+        })
+    }
+
+    /// Address the image's RVAs are relative to: where its first file-backed segment loads.
+    pub fn image_base(&self) -> u64 {
+        self.segments
+            .iter()
+            .filter(|segment| segment.file_size != 0)
+            .map(|segment| segment.vaddr)
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+/// The slices of a universal binary, or `None` if `data` is not one.
+pub fn fat_slices(data: &[u8]) -> Option<Result<Vec<FatArch>, String>> {
+    let r = Reader::new(data, true);
+    let wide = match r.u32(0).ok()? {
+        FAT_MAGIC => false,
+        FAT_MAGIC_64 => true,
+        _ => return None,
+    };
+    let count = r.u32(4).ok()?;
+    if count == 0 || count > MAX_FAT_ARCHS {
+        return None;
+    }
+
+    let parse = || -> Result<Vec<FatArch>, String> {
+        let mut slices = Vec::new();
+        for i in 0..count as usize {
+            let at = 8 + i * if wide { 32 } else { 20 };
+            let (offset, size) = if wide {
+                (r.u64(at + 8)?, r.u64(at + 16)?)
+            } else {
+                (r.u32(at + 8)? as u64, r.u32(at + 12)? as u64)
+            };
+            let name = arch_name(r.u32(at)?, r.u32(at + 4)?);
+            let end = offset
+                .checked_add(size)
+                .filter(|&end| end <= data.len() as u64);
+            if end.is_none() {
+                return Err(format!("{} slice extends past the end of the file", name));
+            }
+            slices.push(FatArch {
+                name,
+                offset: offset as usize,
+                size: size as usize,
+            });
+        }
+        Ok(slices)
+    };
+    Some(parse())
+}
+
+/// The `arch` name used by `lipo` for a CPU type and subtype.
+pub fn arch_name(cpu_type: u32, cpu_subtype: u32) -> String {
+    let subtype = cpu_subtype & CPU_SUBTYPE_MASK;
+    let name = match (cpu_type, subtype) {
+        (CPU_TYPE_X86, _) => "i386",
+        (t, 8) if t == CPU_TYPE_X86 | CPU_ARCH_ABI64 => "x86_64h",
+        (t, _) if t == CPU_TYPE_X86 | CPU_ARCH_ABI64 => "x86_64",
+        (t, 2) if t == CPU_TYPE_ARM | CPU_ARCH_ABI64 => "arm64e",
+        (t, _) if t == CPU_TYPE_ARM | CPU_ARCH_ABI64 => "arm64",
+        (t, _) if t == CPU_TYPE_ARM | CPU_ARCH_ABI64_32 => "arm64_32",
+        (CPU_TYPE_ARM, 6) => "armv6",
+        (CPU_TYPE_ARM, 9) => "armv7",
+        (CPU_TYPE_ARM, 11) => "armv7s",
+        (CPU_TYPE_ARM, 12) => "armv7k",
+        (CPU_TYPE_ARM, _) => "arm",
+        (CPU_TYPE_POWERPC, _) => "ppc",
+        (t, _) if t == CPU_TYPE_POWERPC | CPU_ARCH_ABI64 => "ppc64",
+        _ => return format!("cpu{}:{}", cpu_type, subtype),
+    };
+    name.into()
+}
+
+/// `(is_64, big_endian)` for a thin Mach-O header.
+fn magic(data: &[u8]) -> Option<(bool, bool)> {
+    let bytes: [u8; 4] = data.get(..4)?.try_into().ok()?;
+    match (u32::from_le_bytes(bytes), u32::from_be_bytes(bytes)) {
+        (MH_MAGIC, _) => Some((false, false)),
+        (MH_MAGIC_64, _) => Some((true, false)),
+        (_, MH_MAGIC) => Some((false, true)),
+        (_, MH_MAGIC_64) => Some((true, true)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func::fixtures::{fat, macho64};
+
+    const X86_64: u32 = CPU_TYPE_X86 | CPU_ARCH_ABI64;
+    const ARM64: u32 = CPU_TYPE_ARM | CPU_ARCH_ABI64;
+
+    fn image(cpu_type: u32) -> Vec<u8> {
+        macho64(
+            0x2000,
+            cpu_type,
+            &[
+                ("__PAGEZERO", 0, 0x1_0000_0000, 0, 0),
+                ("__TEXT", 0x1_0000_0000, 0x2000, 0, 0x2000),
+            ],
+        )
+    }
+
+    #[test]
+    fn thin_images_map_segments_and_sections() {
+        let macho = MachO::parse(&image(ARM64)).unwrap();
+        assert!(macho.is_64);
+        assert_eq!(macho.arch, "arm64");
+        assert_eq!(macho.segments.len(), 2);
+        assert_eq!(macho.image_base(), 0x1_0000_0000);
+        assert_eq!(macho.sections.len(), 1);
+        assert_eq!(macho.sections[0].name, "__TEXT,__data");
+        assert_eq!(macho.sections[0].size, 0x2000);
+        assert!(macho.code_signature.is_none());
+    }
+
+    #[test]
+    fn fat_slices_list_each_arch() {
+        let (x86, arm) = (image(X86_64), image(ARM64));
+        let data = fat(&[(X86_64, &x86), (ARM64, &arm)]);
+        let slices = fat_slices(&data).unwrap().unwrap();
+        let names: Vec<&str> = slices.iter().map(|slice| slice.name.as_str()).collect();
+        assert_eq!(names, ["x86_64", "arm64"]);
+        assert_eq!(slices[0].range(), 0x1000..0x3000);
+        assert_eq!(slices[1].range(), 0x3000..0x5000);
+        // Each slice is a thin image of its own
+        let macho = MachO::parse(&data[slices[1].range()]).unwrap();
+        assert_eq!(macho.arch, "arm64");
+    }
+
+    #[test]
+    fn truncated_fat_binaries_are_an_error() {
+        let arm = image(ARM64);
+        let mut data = fat(&[(ARM64, &arm)]);
+        data.truncate(0x2000);
+        let error = fat_slices(&data).unwrap().unwrap_err();
+        assert!(error.contains("arm64 slice extends past the end"));
+    }
+
+    #[test]
+    fn java_class_files_are_not_fat() {
+        // Magic, then minor version 0 and major version 52
+        let class = [0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52];
+        assert!(fat_slices(&class).is_none());
+        assert!(!MachO::is_macho(&class));
+    }
+}
@@ -1,2 +1,24 @@
+pub mod archive;
+pub mod arm64;
+pub mod asm;
+pub mod atomic;
+pub mod backup;
+pub mod binary;
+pub mod config;
+pub mod disasm;
+pub mod dump;
+pub mod elf;
+#[cfg(test)]
+pub mod fixtures;
 pub mod header;
+pub mod hex;
 pub mod logger;
+pub mod macho;
+pub mod patchfile;
+pub mod pattern;
+pub mod pe;
+pub mod port;
+pub mod scan;
+pub mod signature;
+pub mod value;
+pub mod x86;
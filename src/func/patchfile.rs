@@ -0,0 +1,593 @@
+use std::fmt;
+use std::path::Path;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+/// IPS offsets are 24-bit, and a record at `0x454F46` would read as the `EOF` marker.
+const IPS_MAX_OFFSET: usize = 0xFF_FFFF;
+const IPS_EOF_OFFSET: usize = 0x45_4F46;
+const IPS_MAX_RECORD: usize = 0xFFFF;
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// UPS and BPS end with the CRC32s of the source, the target and the patch itself.
+const FOOTER_SIZE: usize = 12;
+/// Most bytes a UPS or BPS result may grow past its source per byte of patch. Zero runs and
+/// target copies let a patch describe more than it holds, but not without bound.
+const MAX_GROWTH_PER_BYTE: usize = 1024;
+
+const BPS_SOURCE_READ: u64 = 0;
+const BPS_TARGET_READ: u64 = 1;
+const BPS_SOURCE_COPY: u64 = 2;
+const BPS_TARGET_COPY: u64 = 3;
+
+/// Patch file formats understood by `export` and `apply-patchfile`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchFormat {
+    Ips,
+    Ups,
+    Bps,
+}
+
+impl PatchFormat {
+    /// The format named by a file's extension.
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("ips") => Ok(PatchFormat::Ips),
+            Some("ups") => Ok(PatchFormat::Ups),
+            Some("bps") => Ok(PatchFormat::Bps),
+            _ => Err(format!(
+                "cannot tell the patch format of '{}'; use a .ips, .ups or .bps extension",
+                path.display()
+            )),
+        }
+    }
+
+    /// The format of an existing patch file, from its magic bytes.
+    pub fn detect(patch: &[u8]) -> Result<Self, String> {
+        if patch.starts_with(IPS_MAGIC) {
+            Ok(PatchFormat::Ips)
+        } else if patch.starts_with(UPS_MAGIC) {
+            Ok(PatchFormat::Ups)
+        } else if patch.starts_with(BPS_MAGIC) {
+            Ok(PatchFormat::Bps)
+        } else {
+            Err("not an IPS, UPS or BPS patch".into())
+        }
+    }
+}
+
+impl fmt::Display for PatchFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchFormat::Ips => write!(f, "IPS"),
+            PatchFormat::Ups => write!(f, "UPS"),
+            PatchFormat::Bps => write!(f, "BPS"),
+        }
+    }
+}
+
+/// A patch in `format` that turns `source` into `target`.
+pub fn create(format: PatchFormat, source: &[u8], target: &[u8]) -> Result<Vec<u8>, String> {
+    match format {
+        PatchFormat::Ips => create_ips(source, target),
+        PatchFormat::Ups => Ok(create_ups(source, target)),
+        PatchFormat::Bps => Ok(create_bps(source, target)),
+    }
+}
+
+/// Applies `patch` to `source`. UPS and BPS patches are refused unless the CRC32s of the
+/// patch, the source and the result all match the ones recorded in the patch.
+pub fn apply(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, String> {
+    match PatchFormat::detect(patch)? {
+        PatchFormat::Ips => apply_ips(patch, source),
+        PatchFormat::Ups => apply_ups(patch, source),
+        PatchFormat::Bps => apply_bps(patch, source),
+    }
+}
+
+/// Ranges where `target` differs from `source`, counting bytes past the end of `source`.
+fn changed_runs(source: &[u8], target: &[u8]) -> Vec<(usize, usize)> {
+    let differs = |i: usize| source.get(i) != Some(&target[i]);
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < target.len() {
+        if !differs(i) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < target.len() && differs(i) {
+            i += 1;
+        }
+        runs.push((start, i));
+    }
+    runs
+}
+
+fn create_ips(source: &[u8], target: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = IPS_MAGIC.to_vec();
+    for (start, end) in changed_runs(source, target) {
+        let mut at = start;
+        while at < end {
+            // A record cannot start where `EOF` would be read, so rewrite the byte before it
+            let record = if at == IPS_EOF_OFFSET { at - 1 } else { at };
+            if record > IPS_MAX_OFFSET {
+                return Err(format!(
+                    "change at offset 0x{:X} is past the 16 MB IPS limit; use UPS or BPS",
+                    record
+                ));
+            }
+            let len = (end - record).min(IPS_MAX_RECORD);
+            out.extend_from_slice(&(record as u32).to_be_bytes()[1..]);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+            out.extend_from_slice(&target[record..record + len]);
+            at = record + len;
+        }
+    }
+    out.extend_from_slice(IPS_EOF);
+    if target.len() < source.len() {
+        if target.len() > IPS_MAX_OFFSET {
+            return Err("truncated size is past the 16 MB IPS limit; use UPS or BPS".into());
+        }
+        out.extend_from_slice(&(target.len() as u32).to_be_bytes()[1..]);
+    }
+    Ok(out)
+}
+
+fn apply_ips(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, String> {
+    let mut r = PatchReader::new(patch, IPS_MAGIC.len());
+    let mut out = source.to_vec();
+    loop {
+        if r.rest().starts_with(IPS_EOF) {
+            r.at += IPS_EOF.len();
+            break;
+        }
+        let offset = r.be(3)?;
+        let len = r.be(2)?;
+        let (bytes, len) = if len == 0 {
+            // Run-length record: a 16-bit count and the byte to repeat
+            let count = r.be(2)?;
+            (vec![r.bytes(1)?[0]; count], count)
+        } else {
+            (r.bytes(len)?.to_vec(), len)
+        };
+        if out.len() < offset + len {
+            out.resize(offset + len, 0);
+        }
+        out[offset..offset + len].copy_from_slice(&bytes);
+    }
+    // An optional 24-bit size after the marker truncates the result
+    if r.rest().len() >= 3 {
+        out.truncate(r.be(3)?);
+    }
+    Ok(out)
+}
+
+fn create_ups(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut out = UPS_MAGIC.to_vec();
+    write_number(&mut out, source.len() as u64);
+    write_number(&mut out, target.len() as u64);
+
+    // Both sides read as zero past their end, so every hunk is a XOR of the two
+    let byte = |data: &[u8], i: usize| data.get(i).copied().unwrap_or(0);
+    let len = source.len().max(target.len());
+    let mut last = 0;
+    let mut i = 0;
+    while i < len {
+        if byte(source, i) == byte(target, i) {
+            i += 1;
+            continue;
+        }
+        write_number(&mut out, (i - last) as u64);
+        while i < len && byte(source, i) != byte(target, i) {
+            out.push(byte(source, i) ^ byte(target, i));
+            i += 1;
+        }
+        // The terminating zero stands for the unchanged byte that ends the hunk
+        out.push(0);
+        i += 1;
+        last = i;
+    }
+    write_footer(&mut out, source, target);
+    out
+}
+
+fn apply_ups(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, String> {
+    let body = check_patch_crc(patch, UPS_MAGIC.len())?;
+    let mut r = PatchReader::new(body, UPS_MAGIC.len());
+    let source_size = r.number()? as usize;
+    let target_size = r.number()? as usize;
+    check_source(patch, source, source_size)?;
+    check_target_size(patch, source, target_size)?;
+
+    let mut out = source.to_vec();
+    out.resize(target_size, 0);
+    let mut at = 0;
+    while !r.rest().is_empty() {
+        at += r.number()? as usize;
+        loop {
+            let xor = r.bytes(1)?[0];
+            if xor == 0 {
+                at += 1;
+                break;
+            }
+            if let Some(byte) = out.get_mut(at) {
+                *byte ^= xor;
+            }
+            at += 1;
+        }
+    }
+    check_target(patch, &out)?;
+    Ok(out)
+}
+
+fn create_bps(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut out = BPS_MAGIC.to_vec();
+    write_number(&mut out, source.len() as u64);
+    write_number(&mut out, target.len() as u64);
+    // No metadata
+    write_number(&mut out, 0);
+
+    // Unchanged bytes are read from the source in place, the rest are written out literally
+    let mut at = 0;
+    for (start, end) in changed_runs(source, target) {
+        if start > at {
+            write_number(&mut out, ((start - at - 1) as u64) << 2 | BPS_SOURCE_READ);
+        }
+        write_number(&mut out, ((end - start - 1) as u64) << 2 | BPS_TARGET_READ);
+        out.extend_from_slice(&target[start..end]);
+        at = end;
+    }
+    if target.len() > at {
+        write_number(
+            &mut out,
+            ((target.len() - at - 1) as u64) << 2 | BPS_SOURCE_READ,
+        );
+    }
+    write_footer(&mut out, source, target);
+    out
+}
+
+fn apply_bps(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, String> {
+    let body = check_patch_crc(patch, BPS_MAGIC.len())?;
+    let mut r = PatchReader::new(body, BPS_MAGIC.len());
+    let source_size = r.number()? as usize;
+    let target_size = r.number()? as usize;
+    let metadata_size = r.number()? as usize;
+    r.bytes(metadata_size)?;
+    check_source(patch, source, source_size)?;
+    check_target_size(patch, source, target_size)?;
+
+    let mut out: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_at: usize = 0;
+    let mut target_at: usize = 0;
+    while !r.rest().is_empty() {
+        let data = r.number()?;
+        let len = (data >> 2) as usize + 1;
+        if out.len() + len > target_size {
+            return Err("BPS patch writes past the target size".into());
+        }
+        match data & 3 {
+            BPS_SOURCE_READ => {
+                let at = out.len();
+                let bytes = source
+                    .get(at..at + len)
+                    .ok_or("BPS patch reads past the end of the source")?;
+                out.extend_from_slice(bytes);
+            }
+            BPS_TARGET_READ => out.extend_from_slice(r.bytes(len)?),
+            BPS_SOURCE_COPY => {
+                source_at = relative(source_at, r.number()?)?;
+                let bytes = source
+                    .get(source_at..source_at + len)
+                    .ok_or("BPS patch copies past the end of the source")?;
+                out.extend_from_slice(bytes);
+                source_at += len;
+            }
+            BPS_TARGET_COPY => {
+                target_at = relative(target_at, r.number()?)?;
+                if target_at >= out.len() {
+                    return Err("BPS patch copies target bytes not written yet".into());
+                }
+                // The copy may overlap what it writes, so it goes a byte at a time
+                for _ in 0..len {
+                    out.push(out[target_at]);
+                    target_at += 1;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+    if out.len() != target_size {
+        return Err(format!(
+            "BPS patch produced {} bytes, expected {}",
+            out.len(),
+            target_size
+        ));
+    }
+    check_target(patch, &out)?;
+    Ok(out)
+}
+
+/// Applies a BPS signed offset: the magnitude shifted left by one, with the sign in bit 0.
+fn relative(at: usize, data: u64) -> Result<usize, String> {
+    let delta = (data >> 1) as usize;
+    let moved = if data & 1 == 1 {
+        at.checked_sub(delta)
+    } else {
+        at.checked_add(delta)
+    };
+    moved.ok_or_else(|| String::from("BPS copy offset out of range"))
+}
+
+/// The patch without its footer, after checking the patch's own CRC32.
+fn check_patch_crc(patch: &[u8], magic_len: usize) -> Result<&[u8], String> {
+    if patch.len() < magic_len + FOOTER_SIZE {
+        return Err("patch is truncated".into());
+    }
+    let body = patch.len() - FOOTER_SIZE;
+    let recorded = footer_crc(patch, 2);
+    let actual = crc32fast::hash(&patch[..patch.len() - 4]);
+    if recorded != actual {
+        return Err(format!(
+            "patch CRC32 mismatch: recorded {:08X}, computed {:08X}; the patch file is damaged",
+            recorded, actual
+        ));
+    }
+    Ok(&patch[..body])
+}
+
+fn check_source(patch: &[u8], source: &[u8], size: usize) -> Result<(), String> {
+    let recorded = footer_crc(patch, 0);
+    let actual = crc32fast::hash(source);
+    if source.len() != size || recorded != actual {
+        return Err(format!(
+            "input does not match the patch source: expected {} bytes with CRC32 {:08X}, found {} bytes with CRC32 {:08X}",
+            size,
+            recorded,
+            source.len(),
+            actual
+        ));
+    }
+    Ok(())
+}
+
+/// Refuses a declared target size that no real patch of this length could produce, before
+/// any memory is set aside for it.
+fn check_target_size(patch: &[u8], source: &[u8], size: usize) -> Result<(), String> {
+    let limit = source
+        .len()
+        .saturating_add(patch.len().saturating_mul(MAX_GROWTH_PER_BYTE));
+    if size > limit {
+        return Err(format!(
+            "patch declares a {} byte result, more than a {} byte patch can produce from a {} byte input",
+            size,
+            patch.len(),
+            source.len()
+        ));
+    }
+    Ok(())
+}
+
+fn check_target(patch: &[u8], target: &[u8]) -> Result<(), String> {
+    let recorded = footer_crc(patch, 1);
+    let actual = crc32fast::hash(target);
+    if recorded != actual {
+        return Err(format!(
+            "patched result CRC32 {:08X} does not match the expected {:08X}",
+            actual, recorded
+        ));
+    }
+    Ok(())
+}
+
+/// The `index`th CRC32 of a UPS/BPS footer: 0 source, 1 target, 2 patch.
+fn footer_crc(patch: &[u8], index: usize) -> u32 {
+    let at = patch.len() - FOOTER_SIZE + index * 4;
+    u32::from_le_bytes(patch[at..at + 4].try_into().unwrap())
+}
+
+fn write_footer(out: &mut Vec<u8>, source: &[u8], target: &[u8]) {
+    out.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+    out.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+    let crc = crc32fast::hash(out);
+    out.extend_from_slice(&crc.to_le_bytes());
+}
+
+/// The variable-length number encoding shared by UPS and BPS: 7 bits per byte, low bits first,
+/// with the high bit marking the last byte and each continuation adding one.
+fn write_number(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let low = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(0x80 | low);
+            break;
+        }
+        out.push(low);
+        value -= 1;
+    }
+}
+
+struct PatchReader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(data: &'a [u8], at: usize) -> Self {
+        PatchReader { data, at }
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.at.min(self.data.len())..]
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.at..self.at.saturating_add(len))
+            .ok_or_else(|| format!("patch is truncated at offset 0x{:X}", self.at))?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    /// A big-endian integer of `len` bytes, as used by IPS.
+    fn be(&mut self, len: usize) -> Result<usize, String> {
+        Ok(self
+            .bytes(len)?
+            .iter()
+            .fold(0, |value, &byte| value << 8 | byte as usize))
+    }
+
+    fn number(&mut self) -> Result<u64, String> {
+        let mut value: u64 = 0;
+        let mut shift: u64 = 1;
+        loop {
+            let byte = self.bytes(1)?[0];
+            value = (byte as u64 & 0x7F)
+                .checked_mul(shift)
+                .and_then(|part| value.checked_add(part))
+                .ok_or("number in patch is too large")?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_shl(7).ok_or("number in patch is too large")?;
+            value = value
+                .checked_add(shift)
+                .ok_or("number in patch is too large")?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [PatchFormat; 3] = [PatchFormat::Ips, PatchFormat::Ups, PatchFormat::Bps];
+
+    fn source() -> Vec<u8> {
+        (0..=255).cycle().take(1024).collect()
+    }
+
+    fn round_trip(source: &[u8], target: &[u8]) {
+        for format in FORMATS {
+            let patch = create(format, source, target).unwrap();
+            assert_eq!(PatchFormat::detect(&patch), Ok(format));
+            assert_eq!(
+                apply(&patch, source).unwrap(),
+                target,
+                "{} round trip",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn changes_round_trip() {
+        let source = source();
+        let mut target = source.clone();
+        target[0] = 0xAA;
+        target[0x100..0x110].fill(0x90);
+        target[1023] = 0;
+        round_trip(&source, &target);
+        round_trip(&source, &source);
+    }
+
+    #[test]
+    fn growing_and_truncating_round_trip() {
+        let source = source();
+        let mut grown = source.clone();
+        grown.extend_from_slice(&[0xCC; 100]);
+        round_trip(&source, &grown);
+        let mut truncated = source[..600].to_vec();
+        truncated[10] ^= 0xFF;
+        round_trip(&source, &truncated);
+    }
+
+    #[test]
+    fn ips_change_at_the_eof_marker_offset() {
+        let source = vec![0; IPS_EOF_OFFSET + 16];
+        let mut target = source.clone();
+        target[IPS_EOF_OFFSET] = 1;
+        let patch = create(PatchFormat::Ips, &source, &target).unwrap();
+        assert_eq!(apply(&patch, &source).unwrap(), target);
+    }
+
+    #[test]
+    fn damaged_patch_is_refused() {
+        let source = source();
+        let mut target = source.clone();
+        target[0x20] = 0xAA;
+        for format in [PatchFormat::Ups, PatchFormat::Bps] {
+            let mut patch = create(format, &source, &target).unwrap();
+            patch[5] ^= 0xFF;
+            let error = apply(&patch, &source).unwrap_err();
+            assert!(error.contains("patch CRC32 mismatch"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn wrong_source_is_refused() {
+        let source = source();
+        let mut target = source.clone();
+        target[0x20] = 0xAA;
+        let mut other = source.clone();
+        other[0x300] = 0xAA;
+        for format in [PatchFormat::Ups, PatchFormat::Bps] {
+            let patch = create(format, &source, &target).unwrap();
+            let error = apply(&patch, &other).unwrap_err();
+            assert!(
+                error.contains("does not match the patch source"),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_target_crc_is_refused() {
+        let source = source();
+        let mut target = source.clone();
+        target[0x20] = 0xAA;
+        for format in [PatchFormat::Ups, PatchFormat::Bps] {
+            let mut patch = create(format, &source, &target).unwrap();
+            // Record another target CRC32, with a patch CRC32 that still matches
+            let crc_at = patch.len() - 8;
+            patch[crc_at] ^= 0xFF;
+            let crc = crc32fast::hash(&patch[..patch.len() - 4]);
+            patch[crc_at + 4..].copy_from_slice(&crc.to_le_bytes());
+            let error = apply(&patch, &source).unwrap_err();
+            assert!(error.contains("does not match the expected"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn impossible_target_sizes_are_refused() {
+        let source = source();
+        for (magic, metadata) in [(UPS_MAGIC, false), (BPS_MAGIC, true)] {
+            let mut patch = magic.to_vec();
+            write_number(&mut patch, source.len() as u64);
+            write_number(&mut patch, 1 << 40);
+            if metadata {
+                write_number(&mut patch, 0);
+            }
+            write_footer(&mut patch, &source, &[]);
+            let error = apply(&patch, &source).unwrap_err();
+            assert!(error.contains("patch declares"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn unknown_patch_format() {
+        assert!(apply(b"NOTAPATCH", &source()).is_err());
+        assert!(PatchFormat::from_path(Path::new("game.xdelta")).is_err());
+        assert_eq!(
+            PatchFormat::from_path(Path::new("game.BPS")),
+            Ok(PatchFormat::Bps)
+        );
+    }
+}
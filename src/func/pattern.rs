@@ -0,0 +1,382 @@
+use std::fmt;
+
+/// Largest repeat count accepted in a `{n-m}` quantifier.
+const MAX_REPEAT: usize = 4096;
+
+/// One byte position of a pattern: matches when any alternative's masked bits agree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ByteMatch {
+    alternatives: Vec<(u8, u8)>,
+}
+
+impl ByteMatch {
+    fn matches(&self, byte: u8) -> bool {
+        self.alternatives
+            .iter()
+            .any(|&(value, mask)| byte & mask == value)
+    }
+
+    /// The byte this always matches, if it matches nothing else.
+    fn literal(&self) -> Option<u8> {
+        match self.alternatives.as_slice() {
+            [(value, 0xFF)] => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token {
+    byte: ByteMatch,
+    min: usize,
+    max: usize,
+}
+
+/// A byte signature such as `48 8B ?? 4? [74|75] E8&F0 ?? {2-8} C3`.
+///
+/// - `AB` matches one byte, `??` (or `?`) any byte.
+/// - `4?` / `?F` match on one nibble only.
+/// - `E8 & F0` matches when the bits set in the mask equal those of the value.
+/// - `[74|75]` matches any of the listed bytes (each may use the forms above).
+/// - `{n}` / `{n-m}` repeat the previous token, so `?? {2-8}` is a gap of 2 to 8 bytes.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    tokens: Vec<Token>,
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let mut tokens: Vec<Token> = Vec::new();
+        let mut chars = pattern.char_indices().peekable();
+
+        while let Some(&(start, c)) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+                continue;
+            }
+
+            match c {
+                '[' => {
+                    let body = take_until(&mut chars, ']')
+                        .ok_or_else(|| format!("unterminated '[' at column {}", start + 1))?;
+                    let alternatives = body
+                        .split('|')
+                        .map(|alt| parse_masked(alt.trim()))
+                        .collect::<Result<Vec<_>, _>>()?
+                        .into_iter()
+                        .flat_map(|byte| byte.alternatives)
+                        .collect();
+                    tokens.push(Token {
+                        byte: ByteMatch { alternatives },
+                        min: 1,
+                        max: 1,
+                    });
+                }
+                '{' => {
+                    let body = take_until(&mut chars, '}')
+                        .ok_or_else(|| format!("unterminated '{{' at column {}", start + 1))?;
+                    let (min, max) = parse_repeat(&body)?;
+                    let token = tokens
+                        .last_mut()
+                        .filter(|token| token.min == 1 && token.max == 1)
+                        .ok_or_else(|| format!("'{{{}}}' must follow a single byte", body))?;
+                    token.min = min;
+                    token.max = max;
+                }
+                _ => {
+                    let mut word = take_word(&mut chars);
+                    // `E8 & F0` may be written with spaces around the `&`
+                    while let Some(&(_, next)) = chars.peek() {
+                        if next.is_whitespace() {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    if chars.peek().is_some_and(|&(_, next)| next == '&') || word.ends_with('&') {
+                        if !word.ends_with('&') {
+                            chars.next();
+                            word.push('&');
+                        }
+                        while chars.peek().is_some_and(|&(_, next)| next.is_whitespace()) {
+                            chars.next();
+                        }
+                        word.push_str(&take_word(&mut chars));
+                    }
+                    tokens.push(Token {
+                        byte: parse_masked(&word)?,
+                        min: 1,
+                        max: 1,
+                    });
+                }
+            }
+        }
+
+        if tokens.is_empty() {
+            return Err("pattern is empty".into());
+        }
+        if tokens.iter().all(|token| token.max == 0) {
+            return Err("pattern can only match zero bytes".into());
+        }
+        Ok(Pattern {
+            source: pattern.split_whitespace().collect::<Vec<_>>().join(" "),
+            tokens,
+        })
+    }
+
+    /// Longest number of bytes a match can span.
+    pub fn max_len(&self) -> usize {
+        self.tokens.iter().map(|token| token.max).sum()
+    }
+
+    /// The exact bytes this pattern matches, if it contains no wildcards of any kind.
+    pub fn literal_bytes(&self) -> Option<Vec<u8>> {
+        let mut bytes = Vec::new();
+        for token in &self.tokens {
+            let literal = token.byte.literal().filter(|_| token.min == token.max)?;
+            bytes.extend(std::iter::repeat_n(literal, token.min));
+        }
+        Some(bytes)
+    }
+
+    /// The longest literal run at a fixed distance from the start of every match, as
+    /// `(distance, bytes)`, used to find candidate positions quickly.
+    pub fn anchor(&self) -> Option<(usize, Vec<u8>)> {
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        let mut run: (usize, Vec<u8>) = (0, Vec::new());
+        let mut position = 0;
+
+        for token in &self.tokens {
+            match token.byte.literal().filter(|_| token.min == token.max) {
+                Some(literal) => {
+                    if run.1.is_empty() {
+                        run.0 = position;
+                    }
+                    run.1.extend(std::iter::repeat_n(literal, token.min));
+                }
+                None => run.1.clear(),
+            }
+            if !run.1.is_empty() && best.as_ref().is_none_or(|b| run.1.len() > b.1.len()) {
+                best = Some(run.clone());
+            }
+            if token.min != token.max {
+                // Anything after a variable-length token has no fixed distance
+                break;
+            }
+            position += token.min;
+        }
+        best
+    }
+
+    /// Length of the match starting at `offset`, if there is one. Variable-length tokens
+    /// prefer the shortest span that lets the rest of the pattern match.
+    pub fn match_at(&self, data: &[u8], offset: usize) -> Option<usize> {
+        self.match_tokens(data, offset, 0).map(|end| end - offset)
+    }
+
+    pub fn matches_at(&self, data: &[u8], offset: usize) -> bool {
+        self.match_at(data, offset).is_some()
+    }
+
+    fn match_tokens(&self, data: &[u8], mut pos: usize, index: usize) -> Option<usize> {
+        let Some(token) = self.tokens.get(index) else {
+            return Some(pos);
+        };
+
+        for _ in 0..token.min {
+            if !token.byte.matches(*data.get(pos)?) {
+                return None;
+            }
+            pos += 1;
+        }
+        let mut count = token.min;
+        loop {
+            if let Some(end) = self.match_tokens(data, pos, index + 1) {
+                return Some(end);
+            }
+            if count == token.max || !token.byte.matches(*data.get(pos)?) {
+                return None;
+            }
+            pos += 1;
+            count += 1;
+        }
+    }
+}
+
+fn take_until(
+    chars: &mut std::iter::Peekable<std::str::CharIndices<'_>>,
+    close: char,
+) -> Option<String> {
+    chars.next();
+    let mut body = String::new();
+    for (_, c) in chars.by_ref() {
+        if c == close {
+            return Some(body);
+        }
+        body.push(c);
+    }
+    None
+}
+
+fn take_word(chars: &mut std::iter::Peekable<std::str::CharIndices<'_>>) -> String {
+    let mut word = String::new();
+    while let Some(&(_, c)) = chars.peek() {
+        if c.is_whitespace() || c == '[' || c == '{' {
+            break;
+        }
+        if c == '&' && !word.is_empty() {
+            word.push(c);
+            chars.next();
+            break;
+        }
+        word.push(c);
+        chars.next();
+    }
+    word
+}
+
+/// Parses `AB`, `??`, `?`, `4?`, `?F` or `VV&MM`.
+fn parse_masked(word: &str) -> Result<ByteMatch, String> {
+    let invalid = || format!("invalid pattern token '{}'", word);
+
+    if let Some((value, mask)) = word.split_once('&') {
+        let value = parse_hex_byte(value.trim()).ok_or_else(invalid)?;
+        let mask = parse_hex_byte(mask.trim()).ok_or_else(invalid)?;
+        return Ok(ByteMatch {
+            alternatives: vec![(value & mask, mask)],
+        });
+    }
+
+    if word == "?" || word == "??" {
+        return Ok(ByteMatch {
+            alternatives: vec![(0, 0)],
+        });
+    }
+
+    let digits: Vec<char> = word.chars().collect();
+    if digits.len() != 2 {
+        return Err(invalid());
+    }
+    let mut value = 0u8;
+    let mut mask = 0u8;
+    for (i, digit) in digits.iter().enumerate() {
+        let shift = if i == 0 { 4 } else { 0 };
+        if *digit == '?' {
+            continue;
+        }
+        let nibble = digit.to_digit(16).ok_or_else(invalid)? as u8;
+        value |= nibble << shift;
+        mask |= 0xF << shift;
+    }
+    Ok(ByteMatch {
+        alternatives: vec![(value, mask)],
+    })
+}
+
+fn parse_hex_byte(text: &str) -> Option<u8> {
+    if text.len() != 2 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u8::from_str_radix(text, 16).ok()
+}
+
+/// Parses the inside of `{n}`, `{n-m}` or `{n,m}`.
+fn parse_repeat(body: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("invalid repeat '{{{}}}'", body);
+    let parse = |text: &str| text.trim().parse::<usize>().map_err(|_| invalid());
+
+    let (min, max) = match body.split_once(['-', ',']) {
+        Some((min, max)) => (parse(min)?, parse(max)?),
+        None => {
+            let count = parse(body)?;
+            (count, count)
+        }
+    };
+    if min > max || max == 0 || max > MAX_REPEAT {
+        return Err(invalid());
+    }
+    Ok((min, max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, data: &[u8]) -> Option<usize> {
+        Pattern::parse(pattern).unwrap().match_at(data, 0)
+    }
+
+    #[test]
+    fn literals_and_wildcards() {
+        assert_eq!(matches("48 8B ?? C3", &[0x48, 0x8B, 0x05, 0xC3]), Some(4));
+        assert_eq!(matches("48 ? C3", &[0x48, 0x00, 0xC3]), Some(3));
+        assert_eq!(matches("48 8B", &[0x48, 0x8C]), None);
+        assert_eq!(matches("48 8B", &[0x48]), None);
+    }
+
+    #[test]
+    fn nibbles_and_masks() {
+        assert_eq!(matches("4?", &[0x4F]), Some(1));
+        assert_eq!(matches("4?", &[0x5F]), None);
+        assert_eq!(matches("?F", &[0x3F]), Some(1));
+        assert_eq!(matches("?F", &[0x3E]), None);
+        assert_eq!(matches("E8 & F0", &[0xE3]), Some(1));
+        assert_eq!(matches("E8&F0", &[0xD8]), None);
+    }
+
+    #[test]
+    fn alternatives() {
+        assert_eq!(matches("[74|75] 05", &[0x75, 0x05]), Some(2));
+        assert_eq!(matches("[74|75] 05", &[0x76, 0x05]), None);
+        assert_eq!(matches("[0F | 7?]", &[0x7A]), Some(1));
+    }
+
+    #[test]
+    fn gaps_take_the_shortest_span() {
+        let pattern = "E8 ?? {1-4} C3";
+        assert_eq!(matches(pattern, &[0xE8, 0x00, 0xC3, 0xC3]), Some(3));
+        assert_eq!(matches(pattern, &[0xE8, 1, 2, 3, 4, 0xC3]), Some(6));
+        assert_eq!(matches(pattern, &[0xE8, 1, 2, 3, 4, 5, 0xC3]), None);
+        assert_eq!(matches("90 {3}", &[0x90; 3]), Some(3));
+        assert_eq!(Pattern::parse("E8 ?? {2-8} C3").unwrap().max_len(), 10);
+    }
+
+    #[test]
+    fn literal_bytes_and_anchor() {
+        let pattern = Pattern::parse("48 8B 05 ?? ?? {2} C3").unwrap();
+        assert_eq!(pattern.literal_bytes(), None);
+        assert_eq!(pattern.anchor(), Some((0, vec![0x48, 0x8B, 0x05])));
+        let pattern = Pattern::parse("90 {2} C3").unwrap();
+        assert_eq!(pattern.literal_bytes(), Some(vec![0x90, 0x90, 0xC3]));
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        for pattern in [
+            "",
+            "9G",
+            "123",
+            "E8 &",
+            "[74|75",
+            "{2}",
+            "?? {2",
+            "?? {0}",
+            "?? {4-2}",
+            "?? {5000}",
+            "?? {2} {3}",
+        ] {
+            assert!(
+                Pattern::parse(pattern).is_err(),
+                "{:?} was accepted",
+                pattern
+            );
+        }
+    }
+}
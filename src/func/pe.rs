@@ -0,0 +1,230 @@
+use crate::func::binary::{Reader, Section, Segment};
+
+const PE32_MAGIC: u16 = 0x10B;
+const PE32_PLUS_MAGIC: u16 = 0x20B;
+const SECTION_HEADER_SIZE: usize = 40;
+
+/// The parts of a PE/COFF image needed to map RVAs and sections to file offsets.
+#[derive(Debug, Clone)]
+pub struct Pe {
+    pub is_64: bool,
+    /// COFF `Machine`, the target instruction set.
+    pub machine: u16,
+    pub image_base: u64,
+    /// File offset of the optional header's `CheckSum` field.
+    pub checksum_offset: usize,
+    pub segments: Vec<Segment>,
+    pub sections: Vec<Section>,
+}
+
+impl Pe {
+    /// True for an `MZ` file whose `e_lfanew` points at a `PE\0\0` signature.
+    pub fn is_pe(data: &[u8]) -> bool {
+        data.starts_with(b"MZ") && pe_header_offset(data).is_ok()
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let r = Reader::new(data, false);
+        let coff = pe_header_offset(data)? + 4;
+        let machine = r.u16(coff)?;
+        let section_count = r.u16(coff + 2)? as usize;
+        let optional_size = r.u16(coff + 16)? as usize;
+        let optional = coff + 20;
+
+        let is_64 = match r.u16(optional)? {
+            PE32_MAGIC => false,
+            PE32_PLUS_MAGIC => true,
+            magic => return Err(format!("unknown PE optional header magic 0x{:X}", magic)),
+        };
+        let image_base = if is_64 {
+            r.u64(optional + 24)?
+        } else {
+            r.u32(optional + 28)? as u64
+        };
+        let headers_size = r.u32(optional + 60)? as u64;
+        let checksum_offset = optional + 64;
+        r.u32(checksum_offset)?;
+
+        // The headers are mapped at the image base ahead of the first section
+        let mut segments = vec![Segment {
+            name: "headers".into(),
+            offset: 0,
+            vaddr: image_base,
+            file_size: headers_size,
+            mem_size: headers_size,
+        }];
+        let mut sections = Vec::new();
+        let table = optional + optional_size;
+        for i in 0..section_count {
+            let at = table + i * SECTION_HEADER_SIZE;
+            let name = r.c_str(at, at + 8)?;
+            let virtual_size = r.u32(at + 8)? as u64;
+            let virtual_address = r.u32(at + 12)? as u64;
+            let raw_size = r.u32(at + 16)? as u64;
+            let raw_offset = r.u32(at + 20)? as u64;
+            // A section the image base pushes past the address space is never loaded
+            let Some(vaddr) = image_base.checked_add(virtual_address) else {
+                continue;
+            };
+
+            // Raw data is padded to the file alignment; only `virtual_size` bytes are loaded
+            let file_size = match virtual_size {
+                0 => raw_size,
+                size => raw_size.min(size),
+            };
+            if raw_offset == 0 {
+                // Uninitialised data has nothing in the file
+                segments.push(Segment {
+                    name: name.clone(),
+                    offset: 0,
+                    vaddr,
+                    file_size: 0,
+                    mem_size: virtual_size,
+                });
+                continue;
+            }
+            segments.push(Segment {
+                name: name.clone(),
+                offset: raw_offset,
+                vaddr,
+                file_size,
+                mem_size: virtual_size.max(file_size),
+            });
+            if file_size != 0 {
+                sections.push(Section {
+                    name,
+                    offset: raw_offset,
+                    vaddr,
+                    size: file_size,
+                });
+            }
+        }
+
+        Ok(Pe {
+            is_64,
+            machine,
+            image_base,
+            checksum_offset,
+            segments,
+            sections,
+        })
+    }
+
+    /// The image checksum as computed by `CheckSumMappedFile`: a folded 16-bit one's
+    /// complement sum of the file with the `CheckSum` field treated as zero, plus its length.
+    pub fn checksum(&self, data: &[u8]) -> u32 {
+        let skip = self.checksum_offset..self.checksum_offset + 4;
+        let mut sum: u64 = 0;
+        for (i, chunk) in data.chunks(2).enumerate() {
+            if skip.contains(&(i * 2)) {
+                continue;
+            }
+            let word = match chunk {
+                [lo, hi] => u16::from_le_bytes([*lo, *hi]),
+                [lo] => *lo as u16,
+                _ => unreachable!(),
+            };
+            sum += word as u64;
+            sum = (sum & 0xFFFF) + (sum >> 16);
+        }
+        sum = (sum & 0xFFFF) + (sum >> 16);
+        (sum as u32).wrapping_add(data.len() as u32)
+    }
+}
+
+fn pe_header_offset(data: &[u8]) -> Result<usize, String> {
+    let r = Reader::new(data, false);
+    if !data.starts_with(b"MZ") {
+        return Err("not a PE file".into());
+    }
+    let offset = r.u32(0x3C)? as usize;
+    match data.get(offset..offset.saturating_add(4)) {
+        Some(b"PE\0\0") => Ok(offset),
+        _ => Err("MZ file without a PE signature".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func::binary::Binary;
+    use crate::func::config::FileFormat;
+    use crate::func::fixtures::pe64;
+
+    const IMAGE_BASE: u64 = 0x1_8000_0000;
+
+    fn binary(image_base: u64) -> Binary {
+        let data = pe64(
+            0x1000,
+            image_base,
+            &[
+                (".text", 0x1000, 0x300, 0x400, 0x400),
+                (".data", 0x2000, 0x100, 0x800, 0x200),
+                (".bss", 0x3000, 0x1000, 0, 0),
+            ],
+        );
+        Binary::load(&data, FileFormat::Auto).unwrap()
+    }
+
+    #[test]
+    fn sections_and_headers() {
+        let Binary::Pe(pe) = binary(IMAGE_BASE) else {
+            panic!("not detected as PE");
+        };
+        assert!(pe.is_64);
+        assert_eq!(pe.image_base, IMAGE_BASE);
+        assert_eq!(pe.checksum_offset, 0x98 + 64);
+        let sections: Vec<_> = pe
+            .sections
+            .iter()
+            .map(|s| (s.name.as_str(), s.offset, s.size))
+            .collect();
+        // Raw data past `virtual_size` is alignment padding
+        assert_eq!(sections, [(".text", 0x400, 0x300), (".data", 0x800, 0x100)]);
+    }
+
+    #[test]
+    fn rvas_map_through_sections() {
+        let binary = binary(IMAGE_BASE);
+        assert_eq!(binary.rva_to_offset(0x1010), Ok(0x410));
+        assert_eq!(binary.rva_to_offset(0x2010), Ok(0x810));
+        assert_eq!(binary.va_to_offset(IMAGE_BASE + 0x1010), Ok(0x410));
+        assert_eq!(binary.rva_to_offset(0x10), Ok(0x10));
+        assert!(binary.rva_to_offset(0x1300).is_err());
+        assert!(binary
+            .rva_to_offset(0x3010)
+            .unwrap_err()
+            .contains("zero-filled"));
+        assert!(binary.rva_to_offset(0x5000).is_err());
+        assert_eq!(binary.offset_to_rva(0x810), Ok(0x2010));
+        assert!(binary.offset_to_rva(0x900).is_err());
+    }
+
+    #[test]
+    fn sections_past_the_address_space_are_not_mapped() {
+        let binary = binary(u64::MAX - 0x1800);
+        assert!(binary.rva_to_offset(0x2010).is_err());
+        assert!(binary.check_write(0x810, 1).is_err());
+        assert_eq!(binary.rva_to_offset(0x1010), Ok(0x410));
+    }
+
+    #[test]
+    fn checksum_folds_carries_and_skips_its_field() {
+        let pe = Pe {
+            is_64: true,
+            machine: 0x8664,
+            image_base: 0,
+            checksum_offset: 0,
+            segments: Vec::new(),
+            sections: Vec::new(),
+        };
+        assert_eq!(
+            pe.checksum(&[0xAA, 0xBB, 0xCC, 0xDD, 0xFF, 0xFF, 0x01, 0x00]),
+            9
+        );
+        assert_eq!(
+            pe.checksum(&[0, 0, 0, 0, 0x34, 0x12, 0x56]),
+            0x1234 + 0x56 + 7
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::func::binary::Isa;
+use crate::func::pattern::Pattern;
+use crate::func::scan::Scanner;
+use crate::func::signature::{
+    format_masked, relocation_masks, unique_signature, MAX_SIGNATURE_LEN,
+};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Bytes of old code compared on each side of a site when the exact signature is lost.
+const FUZZY_CONTEXT: usize = 48;
+/// Size of the pieces of context searched for independently to propose candidate sites.
+const CHUNK_LEN: usize = 8;
+/// Chunks matching more often than this say nothing about where the site went.
+const MAX_CHUNK_HITS: usize = 64;
+/// Candidates with the most votes that are scored in full.
+const SCORED_CANDIDATES: usize = 8;
+/// Share of the context that has to match for a fuzzy match to count at all.
+pub const MIN_FUZZY_SCORE: f64 = 0.6;
+/// Score the runner-up has to trail by for the best candidate to be trusted.
+const AMBIGUITY_MARGIN: f64 = 0.05;
+
+/// Where a site of the old binary is in the new one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Relocation {
+    pub offset: usize,
+    /// `None` when the site's unique signature matched exactly once, otherwise the share of
+    /// the surrounding bytes that still match.
+    pub score: Option<f64>,
+}
+
+/// Finds the `len`-byte site at `site` of `old` in `new`: by its unique signature, covering as
+/// much of the site as a signature can, when that still matches exactly once, and otherwise by
+/// the position where most of the surrounding code lines up, ignoring bytes that change when
+/// code moves.
+pub fn relocate(
+    old: &[u8],
+    new: &[u8],
+    site: usize,
+    len: usize,
+    isa: Isa,
+) -> Result<Relocation, String> {
+    let signature_len = len.min(MAX_SIGNATURE_LEN);
+    if let Ok(signature) = unique_signature(old, site, signature_len, MAX_SIGNATURE_LEN, isa) {
+        let pattern = Pattern::parse(&signature.pattern)?;
+        if let [(start, _)] = Scanner::new(new, vec![pattern]).matches(0, new)[..] {
+            return Ok(Relocation {
+                offset: start + signature.position,
+                score: None,
+            });
+        }
+    }
+
+    let window = site.saturating_sub(FUZZY_CONTEXT)..(site + len + FUZZY_CONTEXT).min(old.len());
+    let masks = relocation_masks(old, window.clone(), isa);
+    let mask = |at: usize| masks[at - window.start];
+
+    // Each distinctive chunk of the old context votes for where the site would be
+    let mut chunks = Vec::new();
+    let mut patterns = Vec::new();
+    for start in window.clone().step_by(CHUNK_LEN) {
+        let end = (start + CHUNK_LEN).min(window.end);
+        let exact: Vec<u8> = (start..end)
+            .filter(|&at| mask(at) == 0xFF)
+            .map(|at| old[at])
+            .collect();
+        if exact.len() < CHUNK_LEN / 2 || exact.iter().all(|&byte| byte == exact[0]) {
+            continue;
+        }
+        patterns.push(Pattern::parse(&format_masked(old, start..end, &mask))?);
+        chunks.push(start);
+    }
+    let scanner = Scanner::new(new, patterns);
+    let mut votes: HashMap<usize, usize> = HashMap::new();
+    for (id, &start) in chunks.iter().enumerate() {
+        let hits = scanner.matches(id, new);
+        if hits.len() > MAX_CHUNK_HITS {
+            continue;
+        }
+        for (hit, _) in hits {
+            if let Some(candidate) = (hit + site).checked_sub(start) {
+                *votes.entry(candidate).or_default() += 1;
+            }
+        }
+    }
+
+    let mut candidates: Vec<(usize, usize)> = votes.into_iter().collect();
+    candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let mut scored: Vec<(usize, f64)> = candidates
+        .into_iter()
+        .take(SCORED_CANDIDATES)
+        .map(|(candidate, _)| {
+            (
+                candidate,
+                similarity(old, new, &window, site, candidate, &mask),
+            )
+        })
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    match scored[..] {
+        [] => Err("no similar code found".into()),
+        [(_, best), ..] if best < MIN_FUZZY_SCORE => Err(format!(
+            "closest match is only {:.0}% similar",
+            best * 100.0
+        )),
+        [(first, best), (second, runner_up), ..] if best - runner_up < AMBIGUITY_MARGIN => {
+            Err(format!(
+                "ambiguous: 0x{:X} and 0x{:X} are both about {:.0}% similar",
+                first,
+                second,
+                best * 100.0
+            ))
+        }
+        [(offset, best), ..] => Ok(Relocation {
+            offset,
+            score: Some(best),
+        }),
+    }
+}
+
+/// Share of the bytes of `old[window]` that still match `new` with the site moved to
+/// `candidate`, comparing only the bits the masks keep.
+fn similarity(
+    old: &[u8],
+    new: &[u8],
+    window: &Range<usize>,
+    site: usize,
+    candidate: usize,
+    mask: &impl Fn(usize) -> u8,
+) -> f64 {
+    let (mut same, mut total) = (0usize, 0usize);
+    for at in window.clone() {
+        let m = mask(at);
+        if m == 0 {
+            continue;
+        }
+        total += 1;
+        let byte = (candidate + at)
+            .checked_sub(site)
+            .and_then(|i| new.get(i).copied());
+        if byte.is_some_and(|byte| (old[at] ^ byte) & m == 0) {
+            same += 1;
+        }
+    }
+    if total == 0 {
+        0.0
+    } else {
+        same as f64 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func::fixtures::distinct;
+
+    #[test]
+    fn exact_relocation() {
+        let old = distinct(2048);
+        let mut new = vec![0xCC; 16];
+        new.extend_from_slice(&old);
+        let relocation = relocate(&old, &new, 0x100, 8, Isa::Unknown).unwrap();
+        assert_eq!(
+            relocation,
+            Relocation {
+                offset: 0x110,
+                score: None
+            }
+        );
+    }
+
+    #[test]
+    fn site_longer_than_a_signature() {
+        let old = distinct(2048);
+        let mut new = vec![0xCC; 16];
+        new.extend_from_slice(&old);
+        let relocation = relocate(&old, &new, 0x100, 300, Isa::Unknown).unwrap();
+        assert_eq!(relocation.offset, 0x110);
+    }
+
+    #[test]
+    fn fuzzy_relocation() {
+        let old = distinct(2048);
+        let mut new = vec![0xCC; 16];
+        new.extend_from_slice(&old);
+        // Change the site itself so its signature no longer matches
+        new[0x110..0x118].fill(0);
+        let relocation = relocate(&old, &new, 0x100, 8, Isa::Unknown).unwrap();
+        assert_eq!(relocation.offset, 0x110);
+        assert!(relocation.score.unwrap() >= MIN_FUZZY_SCORE);
+    }
+}
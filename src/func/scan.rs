@@ -0,0 +1,199 @@
+use crate::func::pattern::Pattern;
+use aho_corasick::{AhoCorasick, MatchKind};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Finds every match of a set of patterns in one pass over the data, then keeps those matches
+/// valid while the data is patched so each pattern never needs a full rescan.
+pub struct Scanner {
+    patterns: Vec<Pattern>,
+    hits: Vec<Vec<usize>>,
+    dirty: Vec<Range<usize>>,
+}
+
+impl Scanner {
+    pub fn new(data: &[u8], patterns: Vec<Pattern>) -> Self {
+        let mut hits = vec![Vec::new(); patterns.len()];
+
+        // Patterns sharing an anchor share one automaton entry
+        let mut anchors: Vec<Vec<u8>> = Vec::new();
+        let mut users: Vec<Vec<usize>> = Vec::new();
+        let mut anchor_ids: HashMap<Vec<u8>, usize> = HashMap::new();
+        let mut distances = vec![0; patterns.len()];
+        for (id, pattern) in patterns.iter().enumerate() {
+            let Some((distance, anchor)) = pattern.anchor() else {
+                // Nothing literal to search for, so every position has to be tried
+                hits[id] = (0..data.len())
+                    .filter(|&start| pattern.matches_at(data, start))
+                    .collect();
+                continue;
+            };
+            distances[id] = distance;
+            let anchor_id = *anchor_ids.entry(anchor.clone()).or_insert_with(|| {
+                anchors.push(anchor);
+                users.push(Vec::new());
+                anchors.len() - 1
+            });
+            users[anchor_id].push(id);
+        }
+
+        if !anchors.is_empty() {
+            let automaton = AhoCorasick::builder()
+                .match_kind(MatchKind::Standard)
+                .build(&anchors)
+                .expect("anchors are plain byte strings");
+            for found in automaton.find_overlapping_iter(data) {
+                for &id in &users[found.pattern().as_usize()] {
+                    let pattern = &patterns[id];
+                    if let Some(start) = found.start().checked_sub(distances[id]) {
+                        if pattern.matches_at(data, start) {
+                            hits[id].push(start);
+                        }
+                    }
+                }
+            }
+            for list in &mut hits {
+                list.sort_unstable();
+            }
+        }
+
+        Scanner {
+            patterns,
+            hits,
+            dirty: Vec::new(),
+        }
+    }
+
+    pub fn pattern(&self, id: usize) -> &Pattern {
+        &self.patterns[id]
+    }
+
+    /// Current matches of pattern `id` in `data` as `(offset, length)`. `data` must be the
+    /// scanned data with every change since reported through `replaced`/`inserted`.
+    pub fn matches(&self, id: usize, data: &[u8]) -> Vec<(usize, usize)> {
+        let pattern = &self.patterns[id];
+        let mut starts: Vec<usize> = self.hits[id].clone();
+
+        // Patched bytes may have created matches that the initial pass could not see
+        for range in &self.dirty {
+            let lo = range
+                .start
+                .saturating_sub(pattern.max_len().saturating_sub(1));
+            let hi = range.end.min(data.len());
+            starts.extend(lo..hi);
+        }
+        starts.sort_unstable();
+        starts.dedup();
+
+        starts
+            .into_iter()
+            .filter_map(|start| pattern.match_at(data, start).map(|len| (start, len)))
+            .collect()
+    }
+
+    /// Records that `len` bytes at `offset` were overwritten.
+    pub fn replaced(&mut self, offset: usize, len: usize) {
+        self.mark_dirty(offset..offset + len);
+    }
+
+    /// Records that `len` bytes were inserted at `offset`, shifting everything after it.
+    pub fn inserted(&mut self, offset: usize, len: usize) {
+        for list in &mut self.hits {
+            for start in list.iter_mut().filter(|start| **start >= offset) {
+                *start += len;
+            }
+        }
+        for range in &mut self.dirty {
+            if range.start >= offset {
+                range.start += len;
+            }
+            if range.end > offset {
+                range.end += len;
+            }
+        }
+        self.mark_dirty(offset..offset + len);
+    }
+
+    fn mark_dirty(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        self.dirty.push(range);
+        self.dirty.sort_by_key(|range| range.start);
+
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(self.dirty.len());
+        for range in self.dirty.drain(..) {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        self.dirty = merged;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scanner(data: &[u8], patterns: &[&str]) -> Scanner {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| Pattern::parse(pattern).unwrap())
+            .collect();
+        Scanner::new(data, patterns)
+    }
+
+    #[test]
+    fn anchors_are_searched_at_their_distance() {
+        let data = [0x11, 0x22, 0xE8, 0x01, 0x33, 0xE8, 0x01, 0xE8, 0x01];
+        let scanner = scanner(&data, &["?? ?? E8 01", "E8 01", "[11|33] ?? E8"]);
+        assert_eq!(scanner.matches(0, &data), [(0, 4), (3, 4), (5, 4)]);
+        assert_eq!(scanner.matches(1, &data), [(2, 2), (5, 2), (7, 2)]);
+        assert_eq!(scanner.matches(2, &data), [(0, 3)]);
+    }
+
+    #[test]
+    fn overlapping_matches_are_all_found() {
+        let data = [0x90; 5];
+        let scanner = scanner(&data, &["90 90 90"]);
+        assert_eq!(scanner.matches(0, &data), [(0, 3), (1, 3), (2, 3)]);
+    }
+
+    #[test]
+    fn patterns_without_an_anchor_try_every_position() {
+        let data = [0x41, 0x52, 0x43, 0x54];
+        let scanner = scanner(&data, &["4? 5?", "[41|43] ?"]);
+        assert_eq!(scanner.matches(0, &data), [(0, 2), (2, 2)]);
+        assert_eq!(scanner.matches(1, &data), [(0, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn replaced_bytes_are_rescanned() {
+        let mut data = vec![0; 16];
+        data[2..4].copy_from_slice(&[0xAB, 0xCD]);
+        let mut scanner = scanner(&data, &["AB CD"]);
+        assert_eq!(scanner.matches(0, &data), [(2, 2)]);
+
+        // One match destroyed, one created across the edge of the change
+        data[2] = 0;
+        scanner.replaced(2, 1);
+        data[9] = 0xAB;
+        data[10] = 0xCD;
+        scanner.replaced(10, 1);
+        assert_eq!(scanner.matches(0, &data), [(9, 2)]);
+    }
+
+    #[test]
+    fn inserted_bytes_shift_earlier_matches() {
+        let mut data = vec![0; 16];
+        data[8..10].copy_from_slice(&[0xAB, 0xCD]);
+        let mut scanner = scanner(&data, &["AB CD"]);
+        scanner.replaced(12, 2);
+
+        data.splice(4..4, [0xAB, 0xCD, 0xAB]);
+        scanner.inserted(4, 3);
+        assert_eq!(scanner.matches(0, &data), [(4, 2), (11, 2)]);
+        assert_eq!(scanner.dirty, [4..7, 15..17]);
+    }
+}
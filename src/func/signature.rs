@@ -0,0 +1,215 @@
+use crate::func::binary::Isa;
+use crate::func::pattern::Pattern;
+use crate::func::scan::Scanner;
+use std::ops::Range;
+
+/// Bytes a signature starts with, before it grows to become unique.
+const MIN_SIGNATURE_LEN: usize = 8;
+/// Longest signature tried before giving up.
+pub const MAX_SIGNATURE_LEN: usize = 128;
+
+/// A `wildcard` pattern that matches exactly one place in a file, and the distance from the
+/// start of that match to the site it was built for (the patch's `position`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub pattern: String,
+    pub position: usize,
+}
+
+/// Grows a signature from the bytes at `offset` until it matches nowhere else in `data`.
+///
+/// The signature covers at least `min_len` bytes from `offset` and extends forward first, so
+/// the site is usually its start; backward context is added once half of `max_len` is used.
+/// Bytes that change whenever code moves, such as call targets, are wildcarded for `isa`.
+pub fn unique_signature(
+    data: &[u8],
+    offset: usize,
+    min_len: usize,
+    max_len: usize,
+    isa: Isa,
+) -> Result<Signature, String> {
+    if offset >= data.len() {
+        return Err(format!("offset 0x{:X} is past the end of the file", offset));
+    }
+    let min_len = min_len.max(MIN_SIGNATURE_LEN);
+    if min_len > max_len {
+        return Err(format!(
+            "a signature of {} bytes is longer than the maximum of {}",
+            min_len, max_len
+        ));
+    }
+    let window = offset.saturating_sub(max_len)..(offset + max_len).min(data.len());
+    let masks = relocation_masks(data, window.clone(), isa);
+    let mask = |at: usize| masks[at - window.start];
+
+    let mut start = offset;
+    let mut end = (offset + min_len).min(data.len());
+
+    // Every other place the current bytes occur, tracked by where its site would be
+    let initial = Pattern::parse(&format_masked(data, start..end, &mask))?;
+    let mut others: Vec<usize> = Scanner::new(data, vec![initial])
+        .matches(0, data)
+        .into_iter()
+        .map(|(at, _)| at)
+        .filter(|&at| at != offset)
+        .collect();
+
+    while !others.is_empty() {
+        if end - start >= max_len {
+            return Err(format!(
+                "no unique signature of up to {} bytes at offset 0x{:X} ({} other matches)",
+                max_len,
+                offset,
+                others.len()
+            ));
+        }
+        let forward = end < data.len() && (end - offset < max_len / 2 || start == 0);
+        if forward {
+            let distance = end - offset;
+            let (byte, m) = (data[end] & mask(end), mask(end));
+            others.retain(|&at| data.get(at + distance).is_some_and(|b| b & m == byte));
+            end += 1;
+        } else if start > 0 {
+            start -= 1;
+            let distance = offset - start;
+            let (byte, m) = (data[start] & mask(start), mask(start));
+            others.retain(|&at| at >= distance && data[at - distance] & m == byte);
+        } else {
+            return Err(format!(
+                "no unique signature at offset 0x{:X}: reached both ends of the file",
+                offset
+            ));
+        }
+    }
+
+    // Wildcards at either end add nothing
+    while end - start > 1 && mask(end - 1) == 0 {
+        end -= 1;
+    }
+    while start < offset && mask(start) == 0 {
+        start += 1;
+    }
+
+    let pattern = format_masked(data, start..end, &mask);
+    let compiled = Pattern::parse(&pattern)?;
+    let hits = Scanner::new(data, vec![compiled]).matches(0, data);
+    if hits.len() != 1 || hits[0].0 != start {
+        return Err(format!(
+            "signature {} matches {} time(s) instead of once",
+            pattern,
+            hits.len()
+        ));
+    }
+    Ok(Signature {
+        pattern,
+        position: offset - start,
+    })
+}
+
+/// Pattern text for `data[range]`, writing each byte in the shortest form its mask allows.
+pub fn format_masked(data: &[u8], range: Range<usize>, mask: &impl Fn(usize) -> u8) -> String {
+    range
+        .map(|at| {
+            let (byte, m) = (data[at], mask(at));
+            match m {
+                0xFF => format!("{:02X}", byte),
+                0x00 => "??".to_string(),
+                0xF0 => format!("{:X}?", byte >> 4),
+                0x0F => format!("?{:X}", byte & 0x0F),
+                _ => format!("{:02X}&{:02X}", byte & m, m),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A mask per byte of `range`: 0xFF for bits to match exactly, 0 for bits that hold
+/// relocation-sensitive immediates.
+///
+/// - x86: the rel32 of `call` (`E8`), `jmp` (`E9`) and `jcc` (`0F 80`-`0F 8F`) wherever such an
+///   opcode byte appears, since instruction boundaries are not known.
+/// - ARM64: the imm26 of `B`/`BL` and the page offset of `ADRP` in each aligned word.
+pub fn relocation_masks(data: &[u8], range: Range<usize>, isa: Isa) -> Vec<u8> {
+    let mut masks = vec![0xFF; range.len()];
+    let mut clear = |at: usize, bits: &[u8]| {
+        for (i, &bits) in bits.iter().enumerate() {
+            if let Some(m) = at
+                .checked_add(i)
+                .filter(|at| range.contains(at))
+                .map(|at| &mut masks[at - range.start])
+            {
+                *m &= !bits;
+            }
+        }
+    };
+
+    match isa {
+        Isa::X86 | Isa::X86_64 => {
+            for at in range.clone() {
+                match (data[at], data.get(at + 1)) {
+                    (0xE8 | 0xE9, _) => clear(at + 1, &[0xFF; 4]),
+                    (0x0F, Some(0x80..=0x8F)) => clear(at + 2, &[0xFF; 4]),
+                    _ => {}
+                }
+            }
+        }
+        Isa::Arm64 => {
+            let first = range.start.next_multiple_of(4);
+            for at in (first..range.end).step_by(4) {
+                let Some(word) = data.get(at..at + 4) else {
+                    break;
+                };
+                let word = u32::from_le_bytes(word.try_into().unwrap());
+                if word & 0x7C00_0000 == 0x1400_0000 {
+                    // B / BL: imm26
+                    clear(at, &0x03FF_FFFFu32.to_le_bytes());
+                } else if word & 0x9F00_0000 == 0x9000_0000 {
+                    // ADRP: immlo (bits 29-30) and immhi (bits 5-23)
+                    clear(at, &0x60FF_FFE0u32.to_le_bytes());
+                }
+            }
+        }
+        Isa::Arm | Isa::Thumb | Isa::Unknown => {}
+    }
+    masks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::func::fixtures::distinct;
+
+    #[test]
+    fn signature_at_unique_site() {
+        let data = distinct(512);
+        let signature = unique_signature(&data, 0x100, 8, MAX_SIGNATURE_LEN, Isa::Unknown).unwrap();
+        assert_eq!(signature.position, 0);
+        assert_eq!(
+            signature.pattern,
+            format_masked(&data, 0x100..0x108, &|_| 0xFF)
+        );
+    }
+
+    #[test]
+    fn signature_grows_past_repeats() {
+        let mut data = vec![0xAA; 64];
+        data.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        data.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 10]);
+        let signature = unique_signature(&data, 64, 8, MAX_SIGNATURE_LEN, Isa::Unknown).unwrap();
+        assert_eq!(signature.pattern, "01 02 03 04 05 06 07 08 09");
+    }
+
+    #[test]
+    fn longer_than_maximum_is_an_error() {
+        let data = distinct(1024);
+        let result = unique_signature(&data, 0x100, 200, MAX_SIGNATURE_LEN, Isa::Unknown);
+        assert!(result.unwrap_err().contains("longer than the maximum"));
+    }
+
+    #[test]
+    fn masks_call_targets() {
+        let data = [0x55, 0xE8, 1, 2, 3, 4, 0xC3];
+        let masks = relocation_masks(&data, 0..data.len(), Isa::X86_64);
+        assert_eq!(masks, [0xFF, 0xFF, 0, 0, 0, 0, 0xFF]);
+    }
+}
@@ -0,0 +1,274 @@
+use serde_json::{Map, Value};
+
+/// Types a `write` patch can encode, listed when an unknown one is given.
+const TYPES: &str =
+    "u8, i8, u16, i16, u32, i32, u64, i64, f32, f64 (+ le/be), utf8, utf16le, utf16be";
+
+/// Encodes a `write` object such as `{"u32le": 100}`, `{"f32": 999.0}` or
+/// `{"utf8": "text", "pad": 0, "len": 16}` as the bytes to write.
+///
+/// Numbers are little-endian unless the type ends in `be`, and must fit the type. Strings are
+/// written as given; `pad` is a byte that fills them up to `len` bytes, or that is appended once
+/// as a terminator when there is no `len`.
+pub fn encode_value(fields: &Map<String, Value>) -> Result<Vec<u8>, String> {
+    let pad = fields.get("pad").map(pad_byte).transpose()?;
+    let len = match fields.get("len") {
+        Some(len) => Some(
+            len.as_u64()
+                .and_then(|len| usize::try_from(len).ok())
+                .ok_or("`len` must be a byte count")?,
+        ),
+        None => None,
+    };
+    let mut values = fields
+        .iter()
+        .filter(|(key, _)| !matches!(key.as_str(), "pad" | "len"));
+    let (Some((kind, value)), None) = (values.next(), values.next()) else {
+        return Err(format!("`write` needs exactly one value type: {}", TYPES));
+    };
+
+    let (base, big_endian) = match (kind.strip_suffix("le"), kind.strip_suffix("be")) {
+        (Some(base), _) if base != "utf16" => (base, false),
+        (_, Some(base)) if base != "utf16" => (base, true),
+        _ => (kind.as_str(), false),
+    };
+    let text = |value: &Value| {
+        value
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("`{}` takes a string", kind))
+    };
+    let string = match kind.as_str() {
+        "utf8" => Some((text(value)?.into_bytes(), 1)),
+        "utf16" | "utf16le" => Some((utf16(&text(value)?, u16::to_le_bytes), 2)),
+        "utf16be" => Some((utf16(&text(value)?, u16::to_be_bytes), 2)),
+        _ => None,
+    };
+    if let Some((bytes, unit)) = string {
+        return pad_string(bytes, unit, pad, len);
+    }
+    if pad.is_some() || len.is_some() {
+        return Err("`pad` and `len` only apply to strings".into());
+    }
+
+    let mut bytes = match base {
+        "f32" => {
+            let float = float(value, kind)?;
+            let narrowed = float as f32;
+            if float.is_finite() && narrowed.is_infinite() {
+                return Err(format!("{:e} overflows f32", float));
+            }
+            narrowed.to_le_bytes().to_vec()
+        }
+        "f64" => float(value, kind)?.to_le_bytes().to_vec(),
+        _ => {
+            let (signed, bits) = match base {
+                "u8" => (false, 8),
+                "i8" => (true, 8),
+                "u16" => (false, 16),
+                "i16" => (true, 16),
+                "u32" => (false, 32),
+                "i32" => (true, 32),
+                "u64" => (false, 64),
+                "i64" => (true, 64),
+                _ => {
+                    return Err(format!(
+                        "unknown value type `{}` (expected {})",
+                        kind, TYPES
+                    ))
+                }
+            };
+            let (min, max) = if signed {
+                (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+            } else {
+                (0, (1i128 << bits) - 1)
+            };
+            let integer = integer(value, kind)?;
+            if !(min..=max).contains(&integer) {
+                return Err(format!(
+                    "{} does not fit in {} (range {} to {})",
+                    integer, base, min, max
+                ));
+            }
+            integer.to_le_bytes()[..bits / 8].to_vec()
+        }
+    };
+    if big_endian {
+        bytes.reverse();
+    }
+    Ok(bytes)
+}
+
+fn utf16(text: &str, encode: fn(u16) -> [u8; 2]) -> Vec<u8> {
+    text.encode_utf16().flat_map(encode).collect()
+}
+
+/// `bytes` of a string made of `unit`-byte code units, padded as `pad` and `len` ask.
+fn pad_string(
+    mut bytes: Vec<u8>,
+    unit: usize,
+    pad: Option<u8>,
+    len: Option<usize>,
+) -> Result<Vec<u8>, String> {
+    match len {
+        Some(len) => {
+            if bytes.len() > len {
+                return Err(format!(
+                    "string is {} bytes, longer than `len` {}",
+                    bytes.len(),
+                    len
+                ));
+            }
+            bytes.resize(len, pad.unwrap_or(0));
+        }
+        None => {
+            if let Some(pad) = pad {
+                bytes.extend(std::iter::repeat_n(pad, unit));
+            }
+        }
+    }
+    if bytes.is_empty() {
+        return Err("string is empty".into());
+    }
+    Ok(bytes)
+}
+
+fn pad_byte(value: &Value) -> Result<u8, String> {
+    value
+        .as_u64()
+        .and_then(|byte| u8::try_from(byte).ok())
+        .ok_or_else(|| "`pad` must be a byte value from 0 to 255".into())
+}
+
+/// A JSON integer, or a string holding one in decimal or `0x` hex for values past what JSON
+/// numbers hold exactly.
+fn integer(value: &Value, kind: &str) -> Result<i128, String> {
+    let invalid = || format!("`{}` takes an integer, found {}", kind, value);
+    match value {
+        Value::Number(number) => number
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| number.as_u64().map(i128::from))
+            .ok_or_else(invalid),
+        Value::String(text) => {
+            let text = text.trim();
+            let (negative, digits) = match text.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, text),
+            };
+            let (radix, magnitude) = match digits
+                .strip_prefix("0x")
+                .or_else(|| digits.strip_prefix("0X"))
+            {
+                Some(hex) => (16, hex),
+                None => (10, digits),
+            };
+            // The only sign allowed after a `-` or `0x` is that `-` itself
+            if (negative || radix == 16) && magnitude.starts_with(['+', '-']) {
+                return Err(invalid());
+            }
+            let magnitude = i128::from_str_radix(magnitude, radix).map_err(|_| invalid())?;
+            Ok(if negative { -magnitude } else { magnitude })
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn float(value: &Value, kind: &str) -> Result<f64, String> {
+    value
+        .as_f64()
+        .ok_or_else(|| format!("`{}` takes a number, found {}", kind, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn encode(value: Value) -> Result<Vec<u8>, String> {
+        encode_value(value.as_object().unwrap())
+    }
+
+    #[test]
+    fn integers_and_floats() {
+        assert_eq!(encode(json!({"u32le": 100})), Ok(vec![100, 0, 0, 0]));
+        assert_eq!(encode(json!({"u16be": 0x1234})), Ok(vec![0x12, 0x34]));
+        assert_eq!(encode(json!({"i8": -1})), Ok(vec![0xFF]));
+        assert_eq!(
+            encode(json!({"u64": "0xFFFFFFFFFFFFFFFF"})),
+            Ok(vec![0xFF; 8])
+        );
+        assert_eq!(
+            encode(json!({"f32": 1.0})),
+            Ok(1.0f32.to_le_bytes().to_vec())
+        );
+        assert_eq!(
+            encode(json!({"f64be": -2.5})),
+            Ok((-2.5f64).to_be_bytes().to_vec())
+        );
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        for value in [
+            json!({"u8": 256}),
+            json!({"u8": -1}),
+            json!({"i8": 128}),
+            json!({"i8": -129}),
+            json!({"u16": 65536}),
+            json!({"i32": 2147483648u64}),
+            json!({"u32": -1}),
+            json!({"u64": "0x10000000000000000"}),
+            json!({"i64": "9223372036854775808"}),
+            json!({"f32": 1e39}),
+            json!({"i32": "--5"}),
+            json!({"i32": "-+5"}),
+            json!({"i32": "0x-5"}),
+            json!({"i32": "-0x-5"}),
+            json!({"u32": "0x+5"}),
+        ] {
+            assert!(encode(value.clone()).is_err(), "{} was accepted", value);
+        }
+        assert_eq!(encode(json!({"i8": -128})), Ok(vec![0x80]));
+        assert_eq!(encode(json!({"u16": 65535})), Ok(vec![0xFF, 0xFF]));
+        assert_eq!(encode(json!({"i16": "-0x10"})), Ok(vec![0xF0, 0xFF]));
+    }
+
+    #[test]
+    fn strings_are_padded() {
+        assert_eq!(encode(json!({"utf8": "hi"})), Ok(b"hi".to_vec()));
+        assert_eq!(
+            encode(json!({"utf8": "hi", "pad": 0})),
+            Ok(b"hi\0".to_vec())
+        );
+        assert_eq!(
+            encode(json!({"utf16le": "hi", "pad": 0})),
+            Ok(vec![b'h', 0, b'i', 0, 0, 0])
+        );
+        assert_eq!(
+            encode(json!({"utf16be": "hi", "len": 6})),
+            Ok(vec![0, b'h', 0, b'i', 0, 0])
+        );
+        assert_eq!(
+            encode(json!({"utf8": "ab", "pad": 0x20, "len": 4})),
+            Ok(b"ab  ".to_vec())
+        );
+    }
+
+    #[test]
+    fn invalid_writes_are_rejected() {
+        for value in [
+            json!({"utf8": "too long", "len": 4}),
+            json!({"utf8": ""}),
+            json!({"utf8": 5}),
+            json!({"utf8": "a", "pad": 256}),
+            json!({"u32": 1, "pad": 0}),
+            json!({"u32": 1.5}),
+            json!({"u24": 1}),
+            json!({"u8": 1, "u16": 1}),
+            json!({}),
+        ] {
+            assert!(encode(value.clone()).is_err(), "{} was accepted", value);
+        }
+    }
+}
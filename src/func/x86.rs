@@ -0,0 +1,338 @@
+use crate::func::asm::{distance, immediate};
+use crate::func::hex::format_bytes;
+
+/// Mnemonics listed when an instruction is not supported.
+const SUPPORTED: &str = "mov, xor, ret, nop [n], int3, jmp, call, j<cc>, invert";
+
+const REX: u8 = 0x40;
+const REX_W: u8 = 0x08;
+const REX_R: u8 = 0x04;
+const REX_B: u8 = 0x01;
+const JMP_REL32: u8 = 0xE9;
+const CALL_REL32: u8 = 0xE8;
+const JCC_REL32: [u8; 2] = [0x0F, 0x80];
+
+/// Condition codes by suffix, in encoding order, with their aliases.
+const CONDITIONS: [&[&str]; 16] = [
+    &["o"],
+    &["no"],
+    &["b", "c", "nae"],
+    &["ae", "nb", "nc"],
+    &["e", "z"],
+    &["ne", "nz"],
+    &["be", "na"],
+    &["a", "nbe"],
+    &["s"],
+    &["ns"],
+    &["p", "pe"],
+    &["np", "po"],
+    &["l", "nge"],
+    &["ge", "nl"],
+    &["le", "ng"],
+    &["g", "nle"],
+];
+
+/// The recommended multi-byte `nop` of each length from 1 to 9.
+const NOPS: [&[u8]; 9] = [
+    &[0x90],
+    &[0x66, 0x90],
+    &[0x0F, 0x1F, 0x00],
+    &[0x0F, 0x1F, 0x40, 0x00],
+    &[0x0F, 0x1F, 0x44, 0x00, 0x00],
+    &[0x66, 0x0F, 0x1F, 0x44, 0x00, 0x00],
+    &[0x0F, 0x1F, 0x80, 0x00, 0x00, 0x00, 0x00],
+    &[0x0F, 0x1F, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00],
+    &[0x66, 0x0F, 0x1F, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00],
+];
+
+const REGISTERS_64: [&str; 16] = [
+    "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15",
+];
+const REGISTERS_32: [&str; 16] = [
+    "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d", "r12d",
+    "r13d", "r14d", "r15d",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Reg {
+    num: u8,
+    wide: bool,
+}
+
+/// Encodes one lowercase instruction at file offset `pc`, for 64-bit code when `long_mode` is
+/// set. `original` holds the bytes at `pc` before patching, which `invert` flips.
+pub fn encode(text: &str, pc: u64, original: &[u8], long_mode: bool) -> Result<Vec<u8>, String> {
+    let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let ops: Vec<&str> = rest
+        .split(',')
+        .map(str::trim)
+        .filter(|op| !op.is_empty())
+        .collect();
+    let reg = |name: &str| register(name, long_mode);
+
+    match (mnemonic, ops.as_slice()) {
+        ("nop", []) => Ok(NOPS[0].to_vec()),
+        ("nop", [len]) => {
+            let len = usize::try_from(immediate(len)?)
+                .ok()
+                .filter(|&len| len > 0)
+                .ok_or("nop length must be at least 1")?;
+            let mut out = Vec::with_capacity(len);
+            while out.len() < len {
+                let size = (len - out.len()).min(NOPS.len());
+                out.extend_from_slice(NOPS[size - 1]);
+            }
+            Ok(out)
+        }
+        ("ret", []) => Ok(vec![0xC3]),
+        ("ret", [pop]) => {
+            let pop = u16::try_from(immediate(pop)?).map_err(|_| "ret takes a 16-bit immediate")?;
+            let mut out = vec![0xC2];
+            out.extend_from_slice(&pop.to_le_bytes());
+            Ok(out)
+        }
+        ("int3", []) => Ok(vec![0xCC]),
+        ("jmp", [target]) => relative(&[JMP_REL32], target, pc),
+        ("call", [target]) => relative(&[CALL_REL32], target, pc),
+        ("invert", []) => invert(original),
+        ("mov", [rd, source]) if is_immediate(source) => mov_immediate(reg(rd)?, source),
+        ("mov" | "xor", [rd, rs]) if !is_immediate(rs) => {
+            let (rd, rs) = (reg(rd)?, reg(rs)?);
+            if rd.wide != rs.wide {
+                return Err("registers must be the same size".into());
+            }
+            let opcode = if mnemonic == "mov" { 0x89 } else { 0x31 };
+            let mut out = rex(rd.wide, rs.num, rd.num);
+            out.extend_from_slice(&[opcode, modrm(rs.num, rd.num)]);
+            Ok(out)
+        }
+        ("xor", [rd, source]) => {
+            let rd = reg(rd)?;
+            let value = immediate(source)?;
+            let mut out = rex(rd.wide, 0, rd.num);
+            if let Ok(byte) = i8::try_from(value) {
+                out.extend_from_slice(&[0x83, modrm(6, rd.num), byte as u8]);
+            } else {
+                let value = imm32(value, rd)?;
+                out.extend_from_slice(&[0x81, modrm(6, rd.num)]);
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            Ok(out)
+        }
+        (jcc, [target]) if jcc.starts_with('j') => {
+            let cond =
+                condition(&jcc[1..]).ok_or_else(|| format!("unknown instruction `{}`", jcc))?;
+            relative(&[JCC_REL32[0], JCC_REL32[1] | cond], target, pc)
+        }
+        ("nop" | "ret" | "int3" | "jmp" | "call" | "invert" | "mov" | "xor", _) => {
+            Err("wrong number of operands".into())
+        }
+        _ => Err(format!(
+            "unsupported instruction `{}` (supported: {})",
+            mnemonic, SUPPORTED
+        )),
+    }
+}
+
+/// `mov` with an immediate: `B8+r imm32` for 32-bit registers, and for 64-bit ones the
+/// sign-extended `C7 /0 imm32` when the value allows it, otherwise `B8+r imm64`.
+fn mov_immediate(rd: Reg, source: &str) -> Result<Vec<u8>, String> {
+    let value = immediate(source)?;
+    let mut out = rex(rd.wide, 0, rd.num);
+    if !rd.wide {
+        out.push(0xB8 + (rd.num & 7));
+        out.extend_from_slice(&imm32(value, rd)?.to_le_bytes());
+    } else if let Ok(value) = i32::try_from(value) {
+        out.extend_from_slice(&[0xC7, modrm(0, rd.num)]);
+        out.extend_from_slice(&value.to_le_bytes());
+    } else {
+        out.push(0xB8 + (rd.num & 7));
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    Ok(out)
+}
+
+/// `opcode` followed by the rel32 from the end of the instruction to `target`.
+fn relative(opcode: &[u8], target: &str, pc: u64) -> Result<Vec<u8>, String> {
+    let len = opcode.len() as i64 + 4;
+    let rel = distance(target, pc)? - len;
+    let rel = i32::try_from(rel)
+        .map_err(|_| format!("target is {} bytes away, beyond the reach of rel32", rel))?;
+    let mut out = opcode.to_vec();
+    out.extend_from_slice(&rel.to_le_bytes());
+    Ok(out)
+}
+
+/// The conditional jump at the start of `original` with its condition reversed, keeping its
+/// size and displacement.
+fn invert(original: &[u8]) -> Result<Vec<u8>, String> {
+    match original {
+        [op @ 0x70..=0x7F, rel8, ..] => Ok(vec![op ^ 1, *rel8]),
+        [0x0F, op @ 0x80..=0x8F, rel32 @ ..] if rel32.len() >= 4 => {
+            let mut out = vec![0x0F, op ^ 1];
+            out.extend_from_slice(&rel32[..4]);
+            Ok(out)
+        }
+        _ => Err(format!(
+            "no conditional jump to invert, found {}",
+            format_bytes(&original[..original.len().min(2)])
+        )),
+    }
+}
+
+/// `value` as the 32-bit immediate of an operation on `rd`, which 64-bit registers
+/// sign-extend.
+fn imm32(value: i64, rd: Reg) -> Result<u32, String> {
+    let fits = if rd.wide {
+        i32::try_from(value).is_ok()
+    } else {
+        (i32::MIN as i64..=u32::MAX as i64).contains(&value)
+    };
+    if !fits {
+        return Err(format!("{} does not fit in a 32-bit immediate", value));
+    }
+    Ok(value as u32)
+}
+
+fn condition(suffix: &str) -> Option<u8> {
+    CONDITIONS
+        .iter()
+        .position(|names| names.contains(&suffix))
+        .map(|cond| cond as u8)
+}
+
+fn register(name: &str, long_mode: bool) -> Result<Reg, String> {
+    let (num, wide) = if let Some(num) = REGISTERS_64.iter().position(|&reg| reg == name) {
+        (num as u8, true)
+    } else if let Some(num) = REGISTERS_32.iter().position(|&reg| reg == name) {
+        (num as u8, false)
+    } else {
+        return Err(format!("unknown register `{}`", name));
+    };
+    if !long_mode && (wide || num >= 8) {
+        return Err(format!("`{}` is only available in 64-bit code", name));
+    }
+    Ok(Reg { num, wide })
+}
+
+/// The REX prefix for an operation of the given width on `reg` (ModRM.reg) and `rm`, if one is
+/// needed.
+fn rex(wide: bool, reg: u8, rm: u8) -> Vec<u8> {
+    let mut prefix = REX;
+    if wide {
+        prefix |= REX_W;
+    }
+    if reg >= 8 {
+        prefix |= REX_R;
+    }
+    if rm >= 8 {
+        prefix |= REX_B;
+    }
+    if prefix == REX {
+        Vec::new()
+    } else {
+        vec![prefix]
+    }
+}
+
+/// Register-direct ModRM byte.
+fn modrm(reg: u8, rm: u8) -> u8 {
+    0xC0 | (reg & 7) << 3 | (rm & 7)
+}
+
+fn is_immediate(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode64(text: &str) -> Result<Vec<u8>, String> {
+        encode(text, 0x1000, &[], true)
+    }
+
+    /// Encodings checked against `llvm-mc -triple=x86_64 -show-encoding`.
+    #[test]
+    fn known_encodings() {
+        for (text, bytes) in [
+            ("ret", &[0xC3][..]),
+            ("ret 8", &[0xC2, 0x08, 0x00]),
+            ("int3", &[0xCC]),
+            ("mov eax, 1", &[0xB8, 0x01, 0x00, 0x00, 0x00]),
+            ("mov r9d, 2", &[0x41, 0xB9, 0x02, 0x00, 0x00, 0x00]),
+            ("mov rax, -1", &[0x48, 0xC7, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF]),
+            (
+                "mov rax, 0x123456789",
+                &[0x48, 0xB8, 0x89, 0x67, 0x45, 0x23, 0x01, 0x00, 0x00, 0x00],
+            ),
+            ("mov r8, rcx", &[0x49, 0x89, 0xC8]),
+            ("mov ecx, edx", &[0x89, 0xD1]),
+            ("xor eax, eax", &[0x31, 0xC0]),
+            ("xor r9, r10", &[0x4D, 0x31, 0xD1]),
+            ("xor ecx, 5", &[0x83, 0xF1, 0x05]),
+            (
+                "xor r9, 0x1000",
+                &[0x49, 0x81, 0xF1, 0x00, 0x10, 0x00, 0x00],
+            ),
+            ("nop 3", &[0x0F, 0x1F, 0x00]),
+        ] {
+            assert_eq!(encode64(text).as_deref(), Ok(bytes), "{}", text);
+        }
+    }
+
+    #[test]
+    fn branches_are_relative_to_the_next_instruction() {
+        for (text, bytes) in [
+            ("jmp 0x1010", &[0xE9, 0x0B, 0x00, 0x00, 0x00][..]),
+            ("call -0x700", &[0xE8, 0xFB, 0xF8, 0xFF, 0xFF]),
+            ("jz +0x20", &[0x0F, 0x84, 0x1A, 0x00, 0x00, 0x00]),
+            ("jnae +6", &[0x0F, 0x82, 0x00, 0x00, 0x00, 0x00]),
+            ("jg 0x1000", &[0x0F, 0x8F, 0xFA, 0xFF, 0xFF, 0xFF]),
+        ] {
+            assert_eq!(encode64(text).as_deref(), Ok(bytes), "{}", text);
+        }
+    }
+
+    #[test]
+    fn long_nops_are_split() {
+        let nop = encode64("nop 11").unwrap();
+        assert_eq!(nop.len(), 11);
+        assert_eq!(nop[..9], *NOPS[8]);
+        assert_eq!(nop[9..], [0x66, 0x90]);
+    }
+
+    #[test]
+    fn invert_flips_the_original_condition() {
+        let invert = |original: &[u8]| encode("invert", 0x1000, original, true);
+        assert_eq!(invert(&[0x74, 0x05, 0x90]), Ok(vec![0x75, 0x05]));
+        assert_eq!(invert(&[0x7F, 0xF0]), Ok(vec![0x7E, 0xF0]));
+        assert_eq!(
+            invert(&[0x0F, 0x8C, 1, 2, 3, 4]),
+            Ok(vec![0x0F, 0x8D, 1, 2, 3, 4])
+        );
+        assert!(invert(&[0x90, 0x90]).is_err());
+        assert!(invert(&[0x0F, 0x84, 1]).is_err());
+        assert!(invert(&[]).is_err());
+    }
+
+    #[test]
+    fn invalid_instructions_are_rejected() {
+        for text in [
+            "mov eax, rbx",
+            "mov eax, 0x100000000",
+            "xor rax, 0x80000000",
+            "ret 0x10000",
+            "nop 0",
+            "jxx +4",
+            "mov rax",
+            "push rax",
+        ] {
+            assert!(encode64(text).is_err(), "{} was accepted", text);
+        }
+        assert!(encode("mov rax, 1", 0, &[], false).is_err());
+        assert!(encode("mov r8d, 1", 0, &[], false).is_err());
+        assert_eq!(encode("mov eax, 1", 0, &[], false).map(|b| b.len()), Ok(5));
+    }
+}
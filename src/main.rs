@@ -1,3 +1,4 @@
+use colored::*;
 use crossterm::{self, execute, terminal};
 use hexsaly::cli::hexsaly;
 use std::io;
@@ -9,5 +10,8 @@ fn main() {
     // Enable ANSI color codes on Windows
     #[cfg(windows)]
     colored::control::set_virtual_terminal(true).unwrap();
-    hexsaly::run().unwrap();
+    if let Err(e) = hexsaly::run() {
+        eprintln!("{}", format!("Error: {}", e).red());
+        std::process::exit(1);
+    }
 }